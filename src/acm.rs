@@ -1,40 +1,50 @@
-use std::error::Error;
 use std::fs::File;
 use std::io::{Read, Seek, SeekFrom, Write};
 use std::path::PathBuf;
 
 use super::directory::Asset;
-use super::utils::{buf_to_le_u32};
-
-pub fn extract_acm(
-    res_file: &mut File, asset: &Asset, path: &mut PathBuf
-) -> Result<bool, Box<dyn Error>> {
-    // Add filename to path
-    path.push(&asset.name);
-    path.set_extension("ACM");
-
-    // If file already exists skip extraction
-    if path.is_file() {
-        return Ok(false);
-    }
+use super::error::{Error, ErrorKind, Result};
+use super::utils::{buf_to_le_u32, read_buf};
 
+pub fn read_acm<R: Read + Seek>(
+    res_file: &mut R, asset: &Asset
+) -> Result<Vec<u8>> {
     // Jump to asset start
     res_file.seek(SeekFrom::Start(asset.offset))?;
 
     // First four bytes of asset is its length
     let mut length = [0;4];
-    res_file.read(&mut length)?;
+    read_buf(res_file, &mut length)?;
     let length = buf_to_le_u32(&length)? as u64;
 
-    // Move to audio data start
-    res_file.seek(SeekFrom::Start(asset.offset + asset.length - length))?;
+    // Move to audio data start, audio can't be longer than asset
+    let start = asset.length.checked_sub(length)
+        .ok_or_else(|| Error::new(ErrorKind::Truncated).at(asset.offset))?;
+    res_file.seek(SeekFrom::Start(asset.offset + start))?;
 
     let mut data = vec![0u8; length as usize];
-    res_file.read(&mut data)?;
+    read_buf(res_file, &mut data)?;
+
+    Ok(data)
+}
+
+pub fn extract_acm<R: Read + Seek>(
+    res_file: &mut R, asset: &Asset, path: &mut PathBuf
+) -> Result<bool> {
+    // Add filename to path
+    path.push(&asset.name);
+    path.set_extension("ACM");
+
+    // If file already exists skip extraction
+    if path.is_file() {
+        return Ok(false);
+    }
+
+    let data = read_acm(res_file, asset)?;
 
     // Write file data
     let mut output = File::create(path)?;
-    output.write_all(&data.as_mut_slice())?;
+    output.write_all(&data)?;
 
     Ok(true)
-}
\ No newline at end of file
+}
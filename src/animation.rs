@@ -0,0 +1,232 @@
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::BufWriter;
+use std::ops::RangeInclusive;
+use std::path::Path;
+use gif::{DisposalMethod, Encoder, Frame, Repeat, SetParameter};
+
+use super::canvas::FrameCanvas;
+use super::error::{Error, ErrorKind, Result};
+use super::indexed::IndexedImage;
+use super::pngfile::write_apng;
+
+// Color shadow pixels are drawn with, animations have no alpha blending
+const SHADOW_COLOR: [u8; 3] = [0, 0, 0];
+
+/// Format animation is saved in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AnimationFormat {
+    Gif,
+    Apng,
+}
+
+impl AnimationFormat {
+    /// Parses "gif" or "apng".
+    pub fn parse(value: &str) -> Option<AnimationFormat> {
+        match value.to_lowercase().as_str() {
+            "gif" => Some(AnimationFormat::Gif),
+            "apng" => Some(AnimationFormat::Apng),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            AnimationFormat::Gif => "GIF",
+            AnimationFormat::Apng => "PNG",
+        }
+    }
+}
+
+/// Settings of animation made from container frames.
+#[derive(Clone, Debug)]
+pub struct AnimationOptions {
+    pub format: AnimationFormat,
+    /// Time every frame is shown for, in milliseconds
+    pub delay: u16,
+    /// Frames to animate, every frame if not set
+    pub frames: Option<RangeInclusive<usize>>,
+}
+
+impl Default for AnimationOptions {
+    fn default() -> AnimationOptions {
+        AnimationOptions {
+            format: AnimationFormat::Gif,
+            delay: 100,
+            frames: None,
+        }
+    }
+}
+
+/// Container frames composed on common canvas.
+#[derive(Clone, Debug)]
+pub struct Animation {
+    pub canvas: FrameCanvas,
+    /// Palette indices of every frame, sized to canvas
+    pub frames: Vec<Vec<u8>>,
+    /// Sprite palette, with shadow color set
+    pub palette: [u8; 768],
+    /// Index of transparent pixels
+    pub transparent: u8,
+}
+
+impl Animation {
+    /// Composes frames in range, drawing sprites over their shadows.
+    ///
+    /// Frames are aligned by their hotspots. Shadow frame is paired with
+    /// sprite frame of the same number.
+    pub fn compose(
+        sprites: &[IndexedImage],
+        shadows: Option<&[IndexedImage]>,
+        frames: Option<RangeInclusive<usize>>
+    ) -> Result<Animation> {
+        let range = get_range(frames, sprites.len())?;
+        let sprites = &sprites[range.clone()];
+        let shadows: Vec<&IndexedImage> = match shadows {
+            Some(shadows) => {
+                range.filter_map(|i| shadows.get(i)).collect()
+            },
+            None => Vec::new(),
+        };
+
+        // Canvas has to fit both sprites and shadows
+        let mut all_frames: Vec<IndexedImage> = sprites.to_vec();
+        all_frames.extend(shadows.iter().map(|&shadow| shadow.clone()));
+        let canvas = FrameCanvas::for_frames(&all_frames);
+
+        // Transparency and shadow need palette entries sprites don't use
+        let (transparent, shadow) = get_free_indices(sprites);
+        let mut palette = sprites[0].palette;
+        let shadow_start = shadow as usize * 3;
+        palette[shadow_start..shadow_start + 3].copy_from_slice(&SHADOW_COLOR);
+
+        let length = (canvas.width * canvas.height) as usize;
+        let mut frames: Vec<Vec<u8>> = Vec::new();
+        for (i, sprite) in sprites.iter().enumerate() {
+            let mut pixels = vec![transparent; length];
+            if let Some(shadow_frame) = shadows.get(i) {
+                let placed = canvas.place(shadow_frame);
+                for (pixel, opaque) in pixels.iter_mut().zip(mask(&placed)) {
+                    if opaque {
+                        *pixel = shadow;
+                    }
+                }
+            }
+
+            let placed = canvas.place(sprite);
+            let opaque = mask(&placed);
+            for ((pixel, index), opaque) in pixels.iter_mut()
+                .zip(placed.pixels.iter())
+                .zip(opaque)
+            {
+                if opaque {
+                    *pixel = *index;
+                }
+            }
+            frames.push(pixels);
+        }
+
+        Ok(Animation { canvas, frames, palette, transparent })
+    }
+
+    /// Saves animation looping forever, in format set by options.
+    pub fn save(&self, path: &Path, options: &AnimationOptions) -> Result<()> {
+        let delay = options.delay;
+        let mut file = BufWriter::new(File::create(path)?);
+        match options.format {
+            AnimationFormat::Gif => self.write_gif(&mut file, delay),
+            AnimationFormat::Apng => write_apng(
+                &mut file,
+                self.canvas.width,
+                self.canvas.height,
+                &self.palette,
+                Some(self.transparent),
+                &self.frames,
+                delay
+            ),
+        }
+    }
+
+    fn write_gif(&self, file: &mut BufWriter<File>, delay: u16) -> Result<()> {
+        // GIF stores size in 2 bytes
+        let (width, height) = (self.canvas.width, self.canvas.height);
+        if width > u16::MAX as u32 || height > u16::MAX as u32 {
+            let message = format!(
+                "animation size {}x{} is too large for GIF", width, height
+            );
+            return Err(Error::new(ErrorKind::InvalidArgument(message)));
+        }
+        let (width, height) = (width as u16, height as u16);
+        // GIF delay is in units of 10 ms, rounded up as 0 means no delay
+        let delay = ((delay as u32 + 9) / 10).max(1) as u16;
+
+        let mut encoder = Encoder::new(file, width, height, &self.palette)?;
+        encoder.set(Repeat::Infinite)?;
+
+        for pixels in &self.frames {
+            let frame = Frame {
+                delay,
+                dispose: DisposalMethod::Background,
+                transparent: Some(self.transparent),
+                width,
+                height,
+                buffer: Cow::Borrowed(pixels),
+                ..Frame::default()
+            };
+            encoder.write_frame(&frame)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Checks that frames range fits container, defaults to every frame.
+fn get_range(
+    frames: Option<RangeInclusive<usize>>, frames_count: usize
+) -> Result<RangeInclusive<usize>> {
+    if frames_count == 0 {
+        let message = String::from("container has no frames");
+        return Err(Error::new(ErrorKind::InvalidArgument(message)));
+    }
+
+    let range = frames.unwrap_or(0..=(frames_count - 1));
+    if range.start() > range.end() || *range.end() >= frames_count {
+        let message = format!(
+            "frames {}-{} out of range ({} frames available)",
+            range.start(), range.end(), frames_count
+        );
+        return Err(Error::new(ErrorKind::InvalidArgument(message)));
+    }
+
+    Ok(range)
+}
+
+/// Finds two palette indices not used by any opaque pixel.
+///
+/// Falls back to 0 and 1 if sprites use whole palette.
+fn get_free_indices(sprites: &[IndexedImage]) -> (u8, u8) {
+    let mut used = [false; 256];
+    for sprite in sprites {
+        for (index, opaque) in sprite.pixels.iter().zip(mask(sprite)) {
+            if opaque {
+                used[*index as usize] = true;
+            }
+        }
+    }
+
+    // Prefer indices from palette end
+    let mut free = (0..=255u8).rev().filter(|index| !used[*index as usize]);
+    let transparent = free.next().unwrap_or(0);
+    let shadow = free.next().unwrap_or(1);
+    (transparent, shadow)
+}
+
+fn mask(image: &IndexedImage) -> Vec<bool> {
+    let mut mask = Vec::with_capacity(image.pixels.len());
+    for y in 0..image.height {
+        for x in 0..image.width {
+            mask.push(image.is_opaque(x, y));
+        }
+    }
+    mask
+}
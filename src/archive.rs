@@ -0,0 +1,437 @@
+use std::fs::File;
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use super::acm::{extract_acm, read_acm};
+use super::animation::{Animation, AnimationOptions};
+use super::directory::{
+    Asset,
+    AssetKind,
+    Directory,
+    get_directory,
+    update_asset_header,
+    update_directory_header,
+    write_directory,
+};
+use super::error::{Error, ErrorKind, Result};
+use super::filter::AssetFilter;
+use super::img::{decode_img, encode_img, extract_img, read_img_info};
+use super::imgcontainer::{
+    decode_img_container,
+    encode_img_container,
+    extract_img_container,
+    read_img_container_info,
+};
+use super::imgmonocontainer::{
+    decode_img_mono_container,
+    encode_img_mono_container,
+    extract_img_mono_container,
+    read_img_mono_container_info,
+};
+use super::imgwithpalette::{
+    decode_img_with_palette,
+    encode_img_with_palette,
+    extract_img_with_palette,
+    read_img_with_palette_info,
+};
+use super::indexed::IndexedImage;
+use super::manifest::AssetInfo;
+use super::palette::get_palettes;
+use super::raw::{extract_raw, read_raw};
+use super::resfile::{
+    check_res_file_header,
+    open_res_file,
+    open_res_file_mut,
+};
+use super::text::extract_txt;
+use super::tiled::TiledFormat;
+use super::typemap::TypeColors;
+
+/// Settings changing how assets are written by `Archive::extract`.
+#[derive(Clone, Debug, Default)]
+pub struct ExtractOptions {
+    /// Place container frames on common canvas, aligned by hotspots
+    pub align_frames: bool,
+    /// Pack container frames into single sprite sheet with descriptor
+    pub atlas: bool,
+    /// Save images as 8-bit PNG with palette instead of RGB(A)
+    pub indexed: bool,
+    /// Save assets as stored in archive, without decoding them
+    pub raw: bool,
+    /// Save WLD heightmap also as raw 16-bit `.r16` file
+    pub raw_heightmap: bool,
+    /// Save WLD terrain as OBJ and glTF meshes
+    pub mesh: bool,
+    /// Save WLD map for Tiled editor, with deduplicated tileset
+    pub tiled: Option<TiledFormat>,
+    /// Colors of WLD sector types in typemap
+    pub type_colors: TypeColors,
+}
+
+/// Opened `RES0` package, eg. `MAX2.RES` or `MAX2.CAF`.
+///
+/// Archive can be read from any seekable source, not only from file.
+pub struct Archive<R = File> {
+    res_file: R,
+    directory: Directory,
+    palettes: Vec<[u8; 768]>,
+}
+
+impl Archive<File> {
+    /// Opens RES package and reads its directory and palettes.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Archive> {
+        Archive::from_reader(open_res_file(path)?)
+    }
+
+    /// Opens RES package for reading and replacing assets.
+    pub fn open_mut<P: AsRef<Path>>(path: P) -> Result<Archive> {
+        Archive::from_reader(open_res_file_mut(path)?)
+    }
+}
+
+impl Archive<Cursor<Vec<u8>>> {
+    /// Reads RES package from data already loaded to memory.
+    pub fn from_bytes(
+        data: Vec<u8>
+    ) -> Result<Archive<Cursor<Vec<u8>>>> {
+        Archive::from_reader(Cursor::new(data))
+    }
+}
+
+impl<R: Read + Seek> Archive<R> {
+    /// Reads directory and palettes of RES package from reader.
+    pub fn from_reader(mut res_file: R) -> Result<Archive<R>> {
+        check_res_file_header(&mut res_file)?;
+        let directory = get_directory(&mut res_file)?;
+        let palettes = get_palettes(&mut res_file, &directory)?;
+
+        Ok(Archive { res_file, directory, palettes })
+    }
+
+    /// Returns underlying reader.
+    pub fn into_inner(self) -> R {
+        self.res_file
+    }
+
+    pub fn directory(&self) -> &Directory {
+        &self.directory
+    }
+
+    /// Lists assets in order they are stored in directory.
+    pub fn assets(&self) -> &[Asset] {
+        &self.directory.assets
+    }
+
+    /// Finds asset by its name.
+    pub fn asset(&self, name: &str) -> Option<&Asset> {
+        self.directory.assets.iter().find(|asset| asset.name == name)
+    }
+
+    /// Lists assets selected by filter.
+    pub fn find_assets(&self, filter: &AssetFilter) -> Vec<Asset> {
+        self.directory.assets.iter()
+            .filter(|asset| filter.matches(asset))
+            .cloned()
+            .collect()
+    }
+
+    /// Palettes stored after directory. Empty if archive has none.
+    pub fn palettes(&self) -> &[[u8; 768]] {
+        &self.palettes
+    }
+
+    /// Reads asset's data as it's extracted: ACM audio without the header
+    /// preceding it, other assets as stored. `read_stored` keeps headers.
+    pub fn read(&mut self, asset: &Asset) -> Result<Vec<u8>> {
+        let result = match asset.kind {
+            AssetKind::Acm => read_acm(&mut self.res_file, asset),
+            _ => read_raw(&mut self.res_file, asset),
+        };
+        result.map_err(|error| error.with_asset(asset))
+    }
+
+    /// Reads asset's data with all its headers, byte for byte as stored.
+    pub fn read_stored(&mut self, asset: &Asset) -> Result<Vec<u8>> {
+        read_raw(&mut self.res_file, asset)
+            .map_err(|error| error.with_asset(asset))
+    }
+
+    /// Reads data of asset with given name.
+    pub fn read_by_name(
+        &mut self, name: &str
+    ) -> Result<Option<Vec<u8>>> {
+        let asset = match self.asset(name) {
+            Some(asset) => asset.clone(),
+            None => return Ok(None),
+        };
+        Ok(Some(self.read(&asset)?))
+    }
+
+    /// Decodes single image asset (type 1 or 5).
+    pub fn decode_image(
+        &mut self, asset: &Asset
+    ) -> Result<IndexedImage> {
+        let result = match asset.kind {
+            AssetKind::ImageWithPalette => {
+                decode_img_with_palette(&mut self.res_file, asset)
+            },
+            AssetKind::Image => decode_img(&mut self.res_file, &self.palettes, asset),
+            _ => Err(not_decodable(asset, "image")),
+        };
+        result.map_err(|error| error.with_asset(asset))
+    }
+
+    /// Decodes every frame of image container asset (type 2 or 3).
+    pub fn decode_frames(
+        &mut self, asset: &Asset
+    ) -> Result<Vec<IndexedImage>> {
+        let result = match asset.kind {
+            AssetKind::SpriteContainer => {
+                decode_img_container(&mut self.res_file, &self.palettes, asset)
+            },
+            AssetKind::ShadowContainer => {
+                decode_img_mono_container(&mut self.res_file, asset)
+            },
+            _ => Err(not_decodable(asset, "image container")),
+        };
+        result.map_err(|error| error.with_asset(asset))
+    }
+
+    /// Encodes decoded asset back to the format it is stored in.
+    ///
+    /// Result equals stored data of assets encoded the same way game files
+    /// are, so it verifies encoders.
+    pub fn reencode(&mut self, asset: &Asset) -> Result<Vec<u8>> {
+        let result = match asset.kind {
+            AssetKind::ImageWithPalette => {
+                let unknown = self.describe(asset)?.unknown;
+                let image = self.decode_image(asset)?;
+                encode_img_with_palette(&image, unknown.unwrap_or_default())
+            },
+            AssetKind::Image => encode_img(&self.decode_image(asset)?),
+            AssetKind::SpriteContainer => {
+                // Palette id is read from header, containers can be empty
+                let palette_id = self.describe(asset)?.palette_id;
+                let frames = self.decode_frames(asset)?;
+                encode_img_container(&frames, palette_id.unwrap_or(0))
+            },
+            AssetKind::ShadowContainer => {
+                encode_img_mono_container(&self.decode_frames(asset)?)
+            },
+            _ => Err(not_encodable(asset)),
+        };
+        result.map_err(|error| error.with_asset(asset))
+    }
+
+    /// Animates frames of sprite container (type 2), over its shadows.
+    pub fn animate(
+        &mut self,
+        sprite: &Asset,
+        shadow: Option<&Asset>,
+        options: &AnimationOptions
+    ) -> Result<Animation> {
+        if sprite.kind != AssetKind::SpriteContainer {
+            return Err(not_decodable(sprite, "sprite container"));
+        }
+        let sprites = self.decode_frames(sprite)?;
+        let shadows = match shadow {
+            Some(shadow) if shadow.kind == AssetKind::ShadowContainer => {
+                Some(self.decode_frames(shadow)?)
+            },
+            Some(shadow) => {
+                return Err(not_decodable(shadow, "shadow container"));
+            },
+            None => None,
+        };
+
+        let frames = options.frames.clone();
+        Animation::compose(&sprites, shadows.as_deref(), frames)
+            .map_err(|error| error.with_asset(sprite))
+    }
+
+    /// Reads palette id, frame sizes and hotspots from asset headers.
+    ///
+    /// Assets that aren't images have no metadata.
+    pub fn describe(&mut self, asset: &Asset) -> Result<AssetInfo> {
+        let res_file = &mut self.res_file;
+        let result = match asset.kind {
+            AssetKind::ImageWithPalette => {
+                read_img_with_palette_info(res_file, asset)
+            },
+            AssetKind::SpriteContainer => {
+                read_img_container_info(res_file, asset)
+            },
+            AssetKind::ShadowContainer => {
+                read_img_mono_container_info(res_file, asset)
+            },
+            AssetKind::Image => read_img_info(res_file, asset),
+            _ => Ok(AssetInfo::default()),
+        };
+        result.map_err(|error| error.with_asset(asset))
+    }
+
+    /// Extracts asset to file or directory under `path`.
+    ///
+    /// Returns `false` if asset was already extracted.
+    pub fn extract(
+        &mut self,
+        asset: &Asset,
+        path: &mut PathBuf,
+        options: &ExtractOptions
+    ) -> Result<bool> {
+        // Stored data is written as is, otherwise asset is decoded
+        let result = if options.raw {
+            extract_raw(&mut self.res_file, asset, path)
+        } else {
+            self.extract_decoded(asset, path, options)
+        };
+        result.map_err(|error| error.with_asset(asset))
+    }
+
+    fn extract_decoded(
+        &mut self,
+        asset: &Asset,
+        path: &mut PathBuf,
+        options: &ExtractOptions
+    ) -> Result<bool> {
+        let res_file = &mut self.res_file;
+        let palettes = &self.palettes;
+
+        // Extract asset using type based algorithm
+        match asset.kind {
+            AssetKind::ImageWithPalette => {
+                extract_img_with_palette(res_file, asset, path, options)
+            },
+            AssetKind::SpriteContainer if !palettes.is_empty() => {
+                extract_img_container(res_file, palettes, asset, path, options)
+            },
+            AssetKind::ShadowContainer => {
+                extract_img_mono_container(res_file, asset, path, options)
+            },
+            AssetKind::Image if !palettes.is_empty() => {
+                extract_img(res_file, palettes, asset, path, options)
+            },
+            AssetKind::StringTable | AssetKind::Text => extract_txt(res_file, asset, path),
+            AssetKind::Acm => extract_acm(res_file, asset, path),
+            _ => extract_raw(res_file, asset, path),
+        }
+    }
+}
+
+impl<R: Read + Write + Seek> Archive<R> {
+    /// Replaces data of asset with given name, stored as it is given.
+    ///
+    /// Data that fits in place of the old one is written over it, larger
+    /// data is appended to the archive. Besides that only asset's directory
+    /// entry is rewritten, every other asset stays as it was.
+    pub fn replace(&mut self, name: &str, data: &[u8]) -> Result<Asset> {
+        let index = self.asset_index(name)?;
+        let asset = self.directory.assets[index].clone();
+        self.replace_data(index, data)
+            .map_err(|error| error.with_asset(&asset))?;
+
+        Ok(self.directory.assets[index].clone())
+    }
+
+    /// Encodes image to format of asset with given name and replaces it.
+    ///
+    /// Type 5 image keeps its hotspot and palette id, type 1 image is stored
+    /// with its palette, after the same 4 unknown bytes the old one has.
+    pub fn replace_image(
+        &mut self, name: &str, image: &IndexedImage
+    ) -> Result<Asset> {
+        let asset = self.directory.assets[self.asset_index(name)?].clone();
+        let data = match asset.kind {
+            AssetKind::ImageWithPalette => {
+                let unknown = self.describe(&asset)?.unknown;
+                encode_img_with_palette(image, unknown.unwrap_or_default())
+            },
+            AssetKind::Image => {
+                let info = self.describe(&asset)?;
+                let mut image = image.clone();
+                if let Some(frame) = info.frames.first() {
+                    image.origin_x = frame.hotspot_x;
+                    image.origin_y = frame.hotspot_y;
+                }
+                image.palette_id = info.palette_id;
+                encode_img(&image)
+            },
+            _ => Err(not_encodable(&asset)),
+        };
+        let data = data.map_err(|error| error.with_asset(&asset))?;
+        self.replace(name, &data)
+    }
+
+    /// Encodes frames to format of container with given name and replaces it.
+    ///
+    /// Frames keep their hotspots, palette id of sprite container is taken
+    /// from the first one. Shadow containers store only opaque pixels.
+    pub fn replace_frames(
+        &mut self, name: &str, frames: &[IndexedImage]
+    ) -> Result<Asset> {
+        let asset = self.directory.assets[self.asset_index(name)?].clone();
+        let palette_id = frames.first()
+            .and_then(|frame| frame.palette_id)
+            .unwrap_or(0);
+        let data = match asset.kind {
+            AssetKind::SpriteContainer => {
+                encode_img_container(frames, palette_id)
+            },
+            AssetKind::ShadowContainer => encode_img_mono_container(frames),
+            _ => Err(not_encodable(&asset)),
+        };
+        let data = data.map_err(|error| error.with_asset(&asset))?;
+        self.replace(name, &data)
+    }
+
+    fn replace_data(&mut self, index: usize, data: &[u8]) -> Result<()> {
+        let res_file = &mut self.res_file;
+        let old_length = self.directory.assets[index].length;
+        let length = data.len() as u64;
+
+        let offset = if length <= old_length {
+            self.directory.assets[index].offset
+        } else {
+            res_file.seek(SeekFrom::End(0))?
+        };
+        res_file.seek(SeekFrom::Start(offset))?;
+        res_file.write_all(data)?;
+
+        let asset = &mut self.directory.assets[index];
+        asset.offset = offset;
+        asset.length = length;
+
+        // Data appended after directory of archive without palettes would
+        // be read as palettes, so directory is moved after it
+        if length > old_length && self.palettes.is_empty() {
+            self.directory.offset = offset + length;
+            write_directory(res_file, &self.directory.assets)?;
+            update_directory_header(res_file, &self.directory)?;
+        } else {
+            update_asset_header(res_file, &self.directory, index)?;
+        }
+        res_file.flush()?;
+
+        Ok(())
+    }
+
+    fn asset_index(&self, name: &str) -> Result<usize> {
+        match self.directory.assets.iter().position(|a| a.name == name) {
+            Some(index) => Ok(index),
+            None => {
+                let message = format!("archive has no asset {}", name);
+                Err(Error::new(ErrorKind::InvalidArgument(message)))
+            },
+        }
+    }
+}
+
+fn not_encodable(asset: &Asset) -> Error {
+    let message = format!("encoding {} assets is not supported", asset.kind);
+    Error::new(ErrorKind::UnsupportedType(message)).at(asset.offset)
+}
+
+fn not_decodable(asset: &Asset, kind: &str) -> Error {
+    let message = format!("asset is not an {}", kind);
+    Error::new(ErrorKind::UnsupportedType(message)).at(asset.offset)
+}
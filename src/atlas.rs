@@ -0,0 +1,180 @@
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::Path;
+use serde::Serialize;
+
+use super::error::Result;
+use super::indexed::{IndexedImage, Transparency};
+
+// Transparent pixels between frames, so they don't bleed when filtered
+const PADDING: u32 = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub w: u32,
+    pub h: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct Size {
+    pub w: u32,
+    pub h: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct Point<T> {
+    pub x: T,
+    pub y: T,
+}
+
+/// Frame entry of TexturePacker "JSON (Array)" descriptor.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AtlasFrame {
+    pub filename: String,
+    /// Frame position in atlas
+    pub frame: Rect,
+    pub rotated: bool,
+    pub trimmed: bool,
+    pub sprite_source_size: Rect,
+    pub source_size: Size,
+    /// Hotspot relative to frame size, eg. `0.5` for its center
+    pub pivot: Point<f32>,
+    /// Hotspot in pixels, as stored in frame header
+    pub hotspot: Point<i32>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct AtlasMeta {
+    pub app: String,
+    pub version: String,
+    /// Atlas image file name
+    pub image: String,
+    pub format: String,
+    pub size: Size,
+    pub scale: String,
+}
+
+/// Sprite sheet descriptor, compatible with TexturePacker JSON.
+#[derive(Clone, Debug, Serialize)]
+pub struct AtlasDescriptor {
+    pub frames: Vec<AtlasFrame>,
+    pub meta: AtlasMeta,
+}
+
+impl AtlasDescriptor {
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let file = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(file, self).map_err(io::Error::from)?;
+        Ok(())
+    }
+}
+
+/// Frames packed into single image.
+#[derive(Clone, Debug)]
+pub struct Atlas {
+    pub image: IndexedImage,
+    /// Position of every frame in atlas image
+    pub rects: Vec<Rect>,
+}
+
+impl Atlas {
+    /// Builds descriptor referring to atlas saved as `image_name`, as
+    /// indexed or RGBA image.
+    pub fn descriptor(
+        &self, frames: &[IndexedImage], image_name: &str, indexed: bool
+    ) -> AtlasDescriptor {
+        let frames = frames.iter().zip(self.rects.iter()).enumerate()
+            .map(|(i, (frame, rect))| AtlasFrame {
+                filename: i.to_string(),
+                frame: *rect,
+                rotated: false,
+                trimmed: false,
+                sprite_source_size: Rect { x: 0, y: 0, w: rect.w, h: rect.h },
+                source_size: Size { w: rect.w, h: rect.h },
+                pivot: Point {
+                    x: relative(frame.origin_x, frame.width),
+                    y: relative(frame.origin_y, frame.height),
+                },
+                hotspot: Point { x: frame.origin_x, y: frame.origin_y },
+            })
+            .collect();
+        // Atlas always has transparency, so it is never saved as RGB
+        let format = if indexed { "INDEXED" } else { "RGBA8888" };
+
+        AtlasDescriptor {
+            frames,
+            meta: AtlasMeta {
+                app: String::from(env!("CARGO_PKG_NAME")),
+                version: String::from(env!("CARGO_PKG_VERSION")),
+                image: image_name.to_string(),
+                format: String::from(format),
+                size: Size { w: self.image.width, h: self.image.height },
+                scale: String::from("1"),
+            },
+        }
+    }
+}
+
+/// Packs frames into rows of atlas that is roughly square.
+pub fn pack_frames(frames: &[IndexedImage]) -> Atlas {
+    // Row can't be narrower than widest frame
+    let area: u32 = frames.iter()
+        .map(|f| (f.width + PADDING) * (f.height + PADDING))
+        .sum();
+    let widest = frames.iter().map(|f| f.width).max().unwrap_or(0);
+    let max_width = widest.max((area as f64).sqrt().ceil() as u32);
+
+    // Place frames left to right, starting new row when width is exceeded
+    let mut rects: Vec<Rect> = Vec::new();
+    let (mut x, mut y, mut row_height) = (0, 0, 0);
+    for frame in frames {
+        if x > 0 && x + frame.width > max_width {
+            x = 0;
+            y += row_height + PADDING;
+            row_height = 0;
+        }
+        rects.push(Rect { x, y, w: frame.width, h: frame.height });
+        x += frame.width + PADDING;
+        row_height = row_height.max(frame.height);
+    }
+
+    // Image can't be empty, even for container without frames
+    let width = rects.iter().map(|r| r.x + r.w).max().unwrap_or(0).max(1);
+    let height = rects.iter().map(|r| r.y + r.h).max().unwrap_or(0).max(1);
+
+    let length = (width * height) as usize;
+    let mut pixels = vec![0u8; length];
+    let mut mask = vec![false; length];
+    for (frame, rect) in frames.iter().zip(rects.iter()) {
+        for y in 0..frame.height {
+            for x in 0..frame.width {
+                let pixel = (rect.x + x + (rect.y + y) * width) as usize;
+                pixels[pixel] = frame.index(x, y);
+                mask[pixel] = frame.is_opaque(x, y);
+            }
+        }
+    }
+
+    let image = IndexedImage {
+        width,
+        height,
+        origin_x: 0,
+        origin_y: 0,
+        pixels,
+        palette_id: frames.first().and_then(|f| f.palette_id),
+        palette: frames.first().map(|f| f.palette).unwrap_or([0; 768]),
+        transparency: Transparency::Mask(mask),
+    };
+
+    Atlas { image, rects }
+}
+
+fn relative(position: i32, size: u32) -> f32 {
+    if size == 0 {
+        return 0.0;
+    }
+    position as f32 / size as f32
+}
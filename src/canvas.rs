@@ -0,0 +1,91 @@
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::Path;
+use serde::Serialize;
+
+use super::error::Result;
+use super::indexed::{IndexedImage, Transparency};
+
+/// Sidecar file written next to aligned frames.
+pub const ANCHOR_FILE: &str = "anchor.json";
+
+/// Canvas fitting every frame of container placed by its hotspot.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct FrameCanvas {
+    pub width: u32,
+    pub height: u32,
+    /// Pixel every frame's hotspot is placed at, may lie outside canvas
+    pub anchor_x: i32,
+    pub anchor_y: i32,
+}
+
+impl FrameCanvas {
+    /// Computes canvas for union of all frames.
+    pub fn for_frames(frames: &[IndexedImage]) -> FrameCanvas {
+        // Frame edges relative to hotspot
+        let left = frames.iter().map(|f| -f.origin_x).min().unwrap_or(0);
+        let top = frames.iter().map(|f| -f.origin_y).min().unwrap_or(0);
+        let right = frames.iter()
+            .map(|f| f.width as i32 - f.origin_x)
+            .max()
+            .unwrap_or(0);
+        let bottom = frames.iter()
+            .map(|f| f.height as i32 - f.origin_y)
+            .max()
+            .unwrap_or(0);
+
+        FrameCanvas {
+            width: (right - left) as u32,
+            height: (bottom - top) as u32,
+            anchor_x: -left,
+            anchor_y: -top,
+        }
+    }
+
+    /// Copies frame onto the canvas, so its hotspot lands on anchor.
+    ///
+    /// Pixels outside of frame are transparent.
+    pub fn place(&self, frame: &IndexedImage) -> IndexedImage {
+        let length = (self.width * self.height) as usize;
+        let mut pixels = vec![0u8; length];
+        let mut mask = vec![false; length];
+
+        // Frame's top left corner on canvas
+        let left = (self.anchor_x - frame.origin_x) as u32;
+        let top = (self.anchor_y - frame.origin_y) as u32;
+        for y in 0..frame.height {
+            for x in 0..frame.width {
+                let pixel = (left + x + (top + y) * self.width) as usize;
+                pixels[pixel] = frame.index(x, y);
+                mask[pixel] = frame.is_opaque(x, y);
+            }
+        }
+
+        IndexedImage {
+            width: self.width,
+            height: self.height,
+            origin_x: self.anchor_x,
+            origin_y: self.anchor_y,
+            pixels,
+            palette_id: frame.palette_id,
+            palette: frame.palette,
+            transparency: Transparency::Mask(mask),
+        }
+    }
+
+    /// Writes canvas size and anchor as JSON.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let file = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(file, self).map_err(io::Error::from)?;
+        Ok(())
+    }
+}
+
+/// Places every frame on common canvas.
+pub fn align_frames(
+    frames: &[IndexedImage]
+) -> (FrameCanvas, Vec<IndexedImage>) {
+    let canvas = FrameCanvas::for_frames(frames);
+    let aligned = frames.iter().map(|frame| canvas.place(frame)).collect();
+    (canvas, aligned)
+}
@@ -1,40 +1,167 @@
-use std::error;
-use std::fs::File;
-use std::io::{Error, ErrorKind, Read, Seek, SeekFrom};
+use std::fmt;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::result;
 use std::str;
 use std::vec::Vec;
+use serde::{Serialize, Serializer};
 
-use super::utils::{buf_to_le_u32, buf_to_le_u64};
+use super::error::{Error, ErrorKind, Result};
+use super::utils::{buf_to_le_u32, buf_to_le_u64, read_buf};
+
+/// Kind of asset, stored in directory as 4 bytes number.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum AssetKind {
+    // Metadata (type 0) is not used in M.A.X 2
+    /// Full-screen image with its own palette (type 1)
+    ImageWithPalette,
+    /// Sprite frames using archive palette (type 2)
+    SpriteContainer,
+    /// Shadow frames without colors (type 3)
+    ShadowContainer,
+    /// Strings (type 4)
+    StringTable,
+    /// Image using archive palette (type 5)
+    Image,
+    /// Text file (type 7)
+    Text,
+    /// ACM audio (type 8)
+    Acm,
+    Unknown(u32),
+}
+
+impl AssetKind {
+    pub fn from_type(type_: u32) -> AssetKind {
+        match type_ {
+            1 => AssetKind::ImageWithPalette,
+            2 => AssetKind::SpriteContainer,
+            3 => AssetKind::ShadowContainer,
+            4 => AssetKind::StringTable,
+            5 => AssetKind::Image,
+            7 => AssetKind::Text,
+            8 => AssetKind::Acm,
+            _ => AssetKind::Unknown(type_),
+        }
+    }
+
+    /// Returns number used for kind in the archive.
+    pub fn type_id(self) -> u32 {
+        match self {
+            AssetKind::ImageWithPalette => 1,
+            AssetKind::SpriteContainer => 2,
+            AssetKind::ShadowContainer => 3,
+            AssetKind::StringTable => 4,
+            AssetKind::Image => 5,
+            AssetKind::Text => 7,
+            AssetKind::Acm => 8,
+            AssetKind::Unknown(type_) => type_,
+        }
+    }
+
+    /// Returns readable name, also used for output directories.
+    pub fn name(self) -> String {
+        let name = match self {
+            AssetKind::ImageWithPalette => "image-with-palette",
+            AssetKind::SpriteContainer => "sprite-container",
+            AssetKind::ShadowContainer => "shadow-container",
+            AssetKind::StringTable => "string-table",
+            AssetKind::Image => "image",
+            AssetKind::Text => "text",
+            AssetKind::Acm => "acm",
+            AssetKind::Unknown(type_) => return format!("unknown-{}", type_),
+        };
+        String::from(name)
+    }
+
+    /// Parses readable name (also "unknown-9" one) or type number.
+    pub fn parse(value: &str) -> Option<AssetKind> {
+        if let Ok(type_) = value.parse::<u32>() {
+            return Some(AssetKind::from_type(type_));
+        }
+        // Kinds with readable name never get "unknown-" one
+        if let Some(number) = value.strip_prefix("unknown-") {
+            return match number.parse::<u32>().map(AssetKind::from_type) {
+                Ok(kind) if kind.is_unknown() => Some(kind),
+                _ => None,
+            };
+        }
+
+        let kinds = [
+            AssetKind::ImageWithPalette,
+            AssetKind::SpriteContainer,
+            AssetKind::ShadowContainer,
+            AssetKind::StringTable,
+            AssetKind::Image,
+            AssetKind::Text,
+            AssetKind::Acm,
+        ];
+        kinds.iter().cloned().find(|kind| kind.name() == value)
+    }
+
+    pub fn is_unknown(self) -> bool {
+        matches!(self, AssetKind::Unknown(_))
+    }
+
+    /// Checks if decoded assets of this kind can be encoded back.
+    pub fn is_encodable(self) -> bool {
+        matches!(
+            self,
+            AssetKind::ImageWithPalette
+                | AssetKind::Image
+                | AssetKind::SpriteContainer
+                | AssetKind::ShadowContainer
+        )
+    }
+}
+
+impl Serialize for AssetKind {
+    fn serialize<S: Serializer>(
+        &self, serializer: S
+    ) -> result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.name())
+    }
+}
+
+impl fmt::Display for AssetKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
 
 // Directory header starts at 6 byte in the file
 const HEADER_OFFSET: u64 = 6;
+// Directory header is 8 bytes long, assets data follows it
+pub const DATA_OFFSET: u64 = HEADER_OFFSET + 8;
+// Every asset header is 13 bytes long, followed by asset name
+const ASSET_HEADER_LEN: usize = 13;
 // Header read error
 const INVALID_HEADER_ERROR: &str = "Failed to read directory header";
 
+#[derive(Clone, Debug)]
 pub struct Asset {
     pub offset: u64,
     pub length: u64,
-    pub type_: u32,
+    pub kind: AssetKind,
     pub name: String,
 }
 
+#[derive(Clone, Debug)]
 pub struct Directory {
     pub offset: u64,
     pub length: u64,
     pub assets: Vec<Asset>,
 }
 
-pub fn get_directory(
-    res_file: &mut File
-) -> Result<Directory, Box<dyn error::Error>> {
+pub fn get_directory<R: Read + Seek>(
+    res_file: &mut R
+) -> Result<Directory> {
     // Seek to header offset
     res_file.seek(SeekFrom::Start(HEADER_OFFSET))?;
 
     // Directory header is two 4 byte long unsigned little endian integers
     let mut header = [0; 8];
-    if res_file.read(&mut header)? != 8 {
-        let err = Error::new(ErrorKind::InvalidData, INVALID_HEADER_ERROR);
-        return Err(Box::new(err));
+    if res_file.read_exact(&mut header).is_err() {
+        let kind = ErrorKind::InvalidHeader(INVALID_HEADER_ERROR.to_string());
+        return Err(Error::new(kind).at(HEADER_OFFSET));
     }
 
     // First 4 bytes store directory's offset
@@ -47,44 +174,132 @@ pub fn get_directory(
     Ok(Directory { offset, length, assets })
 }
 
-fn get_directory_assets(
-    res_file: &mut File, offset: u64, length: u64
-) -> Result<Vec<Asset>, Box<dyn error::Error>> {
+fn get_directory_assets<R: Read + Seek>(
+    res_file: &mut R, offset: u64, length: u64
+) -> Result<Vec<Asset>> {
     // Seek to directory offset
     res_file.seek(SeekFrom::Start(offset))?;
     // Read dictionary length
     let mut headers = vec![0u8; length as usize];
-    res_file.read(&mut headers)?;
+    read_buf(res_file, &mut headers)?;
 
     // Create empty assets list
     let mut assets: Vec<Asset> = Vec::new();
     // Keep extracting data from assets headers
+    let mut header_offset = offset;
     while !headers.is_empty() {
-        assets.push(get_asset_from_headers(&mut headers)?);
+        let asset = get_asset_from_headers(&mut headers, header_offset)?;
+        header_offset += (ASSET_HEADER_LEN + asset.name.len()) as u64;
+        assets.push(asset);
     }
 
     Ok(assets)
 }
 
 fn get_asset_from_headers(
-    headers: &mut Vec<u8>
-) -> Result<Asset, Box<dyn error::Error>> {
+    headers: &mut Vec<u8>, header_offset: u64
+) -> Result<Asset> {
     // First 13 bytes of asset header are constant
-    let header: Vec<_> = headers.drain(..13).collect();
+    if headers.len() < ASSET_HEADER_LEN {
+        return Err(Error::new(ErrorKind::Truncated).at(header_offset));
+    }
+    let header: Vec<_> = headers.drain(..ASSET_HEADER_LEN).collect();
     // Unpack asset header into individual parts
     // First four bytes is asset offset
-    let offset = buf_to_le_u64(&header[0..4]).unwrap();
+    let offset = buf_to_le_u64(&header[0..4])?;
     // Second four bytes is asset data length
-    let length = buf_to_le_u64(&header[4..8]).unwrap();
+    let length = buf_to_le_u64(&header[4..8])?;
     // Last four bytes is asset type
-    let type_ = buf_to_le_u32(&header[8..12]).unwrap();
+    let kind = AssetKind::from_type(buf_to_le_u32(&header[8..12])?);
     // Final byte is asset's name length
     let name_len = header[12] as usize;
 
     // Read asset name
+    let name_offset = header_offset + ASSET_HEADER_LEN as u64;
+    if headers.len() < name_len {
+        return Err(Error::new(ErrorKind::Truncated).at(name_offset));
+    }
     let name: Vec<_> = headers.drain(..name_len).collect();
-    let name = str::from_utf8(&name)?;
-    let name = String::from(name);
+    let name = match str::from_utf8(&name) {
+        Ok(name) => String::from(name),
+        Err(error) => {
+            let kind = ErrorKind::InvalidName(error);
+            return Err(Error::new(kind).at(name_offset));
+        },
+    };
+
+    Ok(Asset { offset, length, kind, name })
+}
+
+/// Writes directory header, which follows file header.
+pub fn write_directory_header<W: Write>(
+    res_file: &mut W, offset: u32, length: u32
+) -> Result<()> {
+    res_file.write_all(&offset.to_le_bytes())?;
+    res_file.write_all(&length.to_le_bytes())?;
 
-    Ok(Asset { offset, length, type_, name })
-}
\ No newline at end of file
+    Ok(())
+}
+
+/// Points directory header of existing file at given directory.
+pub fn update_directory_header<W: Write + Seek>(
+    res_file: &mut W, directory: &Directory
+) -> Result<()> {
+    let offset = checked_u32(directory.offset, "directory")?;
+    let length = checked_u32(directory.length, "directory")?;
+    res_file.seek(SeekFrom::Start(HEADER_OFFSET))?;
+    write_directory_header(res_file, offset, length)
+}
+
+/// Rewrites header of asset at given index in directory of existing file.
+///
+/// Asset name has to be the same, so other headers stay where they are.
+pub fn update_asset_header<W: Write + Seek>(
+    res_file: &mut W, directory: &Directory, index: usize
+) -> Result<()> {
+    let assets = &directory.assets;
+    let header_offset = directory.offset + directory_len(&assets[..index]);
+    res_file.seek(SeekFrom::Start(header_offset))?;
+    write_directory(res_file, &assets[index..=index])
+}
+
+/// Returns length of directory listing given assets.
+pub fn directory_len(assets: &[Asset]) -> u64 {
+    assets.iter()
+        .map(|asset| (ASSET_HEADER_LEN + asset.name.len()) as u64)
+        .sum()
+}
+
+/// Writes asset headers in the layout `get_directory` reads them.
+pub fn write_directory<W: Write>(
+    res_file: &mut W, assets: &[Asset]
+) -> Result<()> {
+    for asset in assets {
+        // Name length has to fit single byte
+        if asset.name.is_empty() || asset.name.len() > 255 {
+            let message = format!(
+                "asset name \"{}\" is not 1 to 255 bytes long", asset.name
+            );
+            return Err(Error::new(ErrorKind::InvalidArgument(message)));
+        }
+
+        let offset = checked_u32(asset.offset, &asset.name)?;
+        let length = checked_u32(asset.length, &asset.name)?;
+        res_file.write_all(&offset.to_le_bytes())?;
+        res_file.write_all(&length.to_le_bytes())?;
+        res_file.write_all(&asset.kind.type_id().to_le_bytes())?;
+        res_file.write_all(&[asset.name.len() as u8])?;
+        res_file.write_all(asset.name.as_bytes())?;
+    }
+
+    Ok(())
+}
+
+/// Checks that offset or length of named part fits 4 bytes.
+fn checked_u32(value: u64, name: &str) -> Result<u32> {
+    if value > u32::MAX as u64 {
+        let message = format!("{} doesn't fit 4 GB archive", name);
+        return Err(Error::new(ErrorKind::InvalidArgument(message)));
+    }
+    Ok(value as u32)
+}
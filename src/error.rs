@@ -0,0 +1,144 @@
+use std::error;
+use std::fmt;
+use std::io;
+use std::result;
+use std::str::Utf8Error;
+use image::ImageError;
+
+use super::directory::Asset;
+
+pub type Result<T> = result::Result<T, Error>;
+
+/// What went wrong while reading or writing game data.
+#[derive(Debug)]
+pub enum ErrorKind {
+    /// File or asset doesn't start with expected header
+    InvalidHeader(String),
+    /// Data ended before everything was read
+    Truncated,
+    /// Asset refers to palette that doesn't exist in the archive
+    PaletteOutOfRange { palette_id: usize, palettes_count: usize },
+    /// Compressed image data is malformed
+    InvalidRle(String),
+    /// Asset name stored in directory is not valid UTF-8
+    InvalidName(Utf8Error),
+    /// Asset can't be decoded in requested way
+    UnsupportedType(String),
+    /// Asset filter pattern or type is malformed
+    InvalidFilter(String),
+    /// Option passed to decoder or encoder is out of range
+    InvalidArgument(String),
+    Io(io::Error),
+    Image(ImageError),
+}
+
+/// Error with optional asset and offset that caused it.
+#[derive(Debug)]
+pub struct Error {
+    pub kind: ErrorKind,
+    /// Asset that was read when error occurred
+    pub asset: Option<Asset>,
+    /// Offset in the file at which error occurred
+    pub offset: Option<u64>,
+}
+
+impl Error {
+    pub fn new(kind: ErrorKind) -> Error {
+        Error { kind, asset: None, offset: None }
+    }
+
+    /// Sets error offset, unless more precise one was already set.
+    pub fn at(mut self, offset: u64) -> Error {
+        if self.offset.is_none() {
+            self.offset = Some(offset);
+        }
+        self
+    }
+
+    /// Sets asset that caused error, unless it was already set.
+    pub fn with_asset(mut self, asset: &Asset) -> Error {
+        if self.asset.is_none() {
+            self.asset = Some(asset.clone());
+        }
+        self
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ErrorKind::InvalidHeader(message) => {
+                write!(f, "invalid header: {}", message)
+            },
+            ErrorKind::Truncated => write!(f, "unexpected end of data"),
+            ErrorKind::PaletteOutOfRange { palette_id, palettes_count } => {
+                write!(
+                    f,
+                    "palette #{} out of range ({} palettes available)",
+                    palette_id,
+                    palettes_count
+                )
+            },
+            ErrorKind::InvalidRle(message) => {
+                write!(f, "invalid RLE data: {}", message)
+            },
+            ErrorKind::InvalidName(error) => {
+                write!(f, "asset name is not valid UTF-8: {}", error)
+            },
+            ErrorKind::UnsupportedType(message) => write!(f, "{}", message),
+            ErrorKind::InvalidFilter(message) => {
+                write!(f, "invalid filter: {}", message)
+            },
+            ErrorKind::InvalidArgument(message) => {
+                write!(f, "invalid argument: {}", message)
+            },
+            ErrorKind::Io(error) => write!(f, "I/O error: {}", error),
+            ErrorKind::Image(error) => write!(f, "image error: {}", error),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(asset) = &self.asset {
+            write!(f, "{} ({}): ", asset.name, asset.kind)?;
+        }
+        write!(f, "{}", self.kind)?;
+        if let Some(offset) = self.offset {
+            write!(f, " at offset {}", offset)?;
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match &self.kind {
+            ErrorKind::InvalidName(error) => Some(error),
+            ErrorKind::Io(error) => Some(error),
+            ErrorKind::Image(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<ErrorKind> for Error {
+    fn from(kind: ErrorKind) -> Error {
+        Error::new(kind)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Error {
+        match error.kind() {
+            io::ErrorKind::UnexpectedEof => Error::new(ErrorKind::Truncated),
+            _ => Error::new(ErrorKind::Io(error)),
+        }
+    }
+}
+
+impl From<ImageError> for Error {
+    fn from(error: ImageError) -> Error {
+        Error::new(ErrorKind::Image(error))
+    }
+}
@@ -0,0 +1,144 @@
+use glob::Pattern;
+use regex::Regex;
+
+use super::directory::{Asset, AssetKind};
+use super::error::{Error, ErrorKind, Result};
+
+/// Pattern asset name is matched against.
+///
+/// Glob patterns are matched regardless of case.
+#[derive(Clone, Debug)]
+pub enum NamePattern {
+    Glob(Pattern),
+    Regex(Regex),
+}
+
+impl NamePattern {
+    pub fn matches(&self, name: &str) -> bool {
+        match self {
+            NamePattern::Glob(pattern) => {
+                pattern.matches(&name.to_lowercase())
+            },
+            NamePattern::Regex(regex) => regex.is_match(name),
+        }
+    }
+}
+
+/// Asset kind to include or exclude.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TypeSelector {
+    Kind(AssetKind),
+    /// Every kind extractor doesn't know
+    Unknown,
+}
+
+impl TypeSelector {
+    /// Parses type number, kind name or "unknown" keyword.
+    pub fn parse(value: &str) -> Result<TypeSelector> {
+        if value.eq_ignore_ascii_case("unknown") {
+            return Ok(TypeSelector::Unknown);
+        }
+
+        match AssetKind::parse(value) {
+            Some(kind) => Ok(TypeSelector::Kind(kind)),
+            None => {
+                let message = format!("\"{}\" is not an asset type", value);
+                Err(Error::new(ErrorKind::InvalidFilter(message)))
+            },
+        }
+    }
+
+    pub fn matches(&self, kind: AssetKind) -> bool {
+        match self {
+            TypeSelector::Kind(selected) => *selected == kind,
+            TypeSelector::Unknown => kind.is_unknown(),
+        }
+    }
+}
+
+/// Selects assets to process by name, type and archive they come from.
+///
+/// Empty filter matches everything.
+#[derive(Clone, Debug, Default)]
+pub struct AssetFilter {
+    /// Asset name has to match any of those
+    pub names: Vec<NamePattern>,
+    /// Asset type has to match any of those
+    pub include_types: Vec<TypeSelector>,
+    /// Asset type can't match any of those
+    pub exclude_types: Vec<TypeSelector>,
+    /// Archive file name has to match any of those
+    pub sources: Vec<Pattern>,
+}
+
+impl AssetFilter {
+    pub fn new() -> AssetFilter {
+        AssetFilter::default()
+    }
+
+    /// Adds glob pattern, eg. `"TANK*"`.
+    pub fn name_glob(mut self, pattern: &str) -> Result<AssetFilter> {
+        self.names.push(NamePattern::Glob(parse_glob(pattern)?));
+        Ok(self)
+    }
+
+    /// Adds regular expression, eg. `"^TANK(SHD)?$"`.
+    pub fn name_regex(mut self, pattern: &str) -> Result<AssetFilter> {
+        match Regex::new(pattern) {
+            Ok(regex) => self.names.push(NamePattern::Regex(regex)),
+            Err(error) => {
+                let kind = ErrorKind::InvalidFilter(error.to_string());
+                return Err(Error::new(kind));
+            },
+        }
+        Ok(self)
+    }
+
+    pub fn include_type(mut self, type_: TypeSelector) -> AssetFilter {
+        self.include_types.push(type_);
+        self
+    }
+
+    pub fn exclude_type(mut self, type_: TypeSelector) -> AssetFilter {
+        self.exclude_types.push(type_);
+        self
+    }
+
+    /// Adds glob pattern for archive file name, eg. `"MAX2.RES"`.
+    pub fn source(mut self, pattern: &str) -> Result<AssetFilter> {
+        self.sources.push(parse_glob(pattern)?);
+        Ok(self)
+    }
+
+    /// Checks if assets from archive with given file name are selected.
+    pub fn matches_source(&self, source: &str) -> bool {
+        self.sources.is_empty() || self.sources.iter().any(|pattern| {
+            pattern.matches(&source.to_lowercase())
+        })
+    }
+
+    /// Checks if asset is selected.
+    pub fn matches(&self, asset: &Asset) -> bool {
+        let name_matches = self.names.is_empty() || self.names.iter().any(
+            |pattern| pattern.matches(&asset.name)
+        );
+        let type_included = self.include_types.is_empty()
+            || self.include_types.iter().any(|t| t.matches(asset.kind));
+        let type_excluded = self.exclude_types.iter().any(
+            |t| t.matches(asset.kind)
+        );
+
+        name_matches && type_included && !type_excluded
+    }
+}
+
+fn parse_glob(pattern: &str) -> Result<Pattern> {
+    // Names are lowercased before matching to ignore case
+    match Pattern::new(&pattern.to_lowercase()) {
+        Ok(pattern) => Ok(pattern),
+        Err(error) => {
+            let kind = ErrorKind::InvalidFilter(error.to_string());
+            Err(Error::new(kind))
+        },
+    }
+}
@@ -1,81 +1,160 @@
-use std::error::Error;
-use std::fs::File;
 use std::io::{Read, Seek, SeekFrom};
 use std::path::PathBuf;
-use std::vec::Vec;
-use image::{ImageBuffer, Rgba, RgbaImage};
 
+use super::archive::ExtractOptions;
 use super::directory::Asset;
-use super::utils::buf_to_le_u32;
+use super::indexed::{IndexedImage, Transparency};
+use super::error::{Error, ErrorKind, Result};
+use super::manifest::{AssetInfo, FrameInfo};
+use super::palette::find_palette;
+use super::utils::{buf_to_le_i32, buf_to_le_u32, read_buf};
 
 // Asset header: 10 bytes
 const HEADER_LEN: usize = 10;
 
-pub fn extract_img(
-    res_file: &mut File,
-    palettes: &Vec<[u8; 768]>,
-    asset: &Asset, 
-    path: &mut PathBuf
-) -> Result<bool, Box<dyn Error>> {
-    // Add filename to path
-    path.push(&asset.name);
-    path.set_extension("PNG");
-
-    // If file already exists skip extraction
-    if path.is_file() {
-        return Ok(false);
-    }
-
+pub fn decode_img<R: Read + Seek>(
+    res_file: &mut R,
+    palettes: &[[u8; 768]],
+    asset: &Asset
+) -> Result<IndexedImage> {
     // Jump to asset start
     res_file.seek(SeekFrom::Start(asset.offset))?;
 
     // Read 10 bytes of asset header
     let mut header = [0;HEADER_LEN];
-    res_file.read(&mut header)?;
+    read_buf(res_file, &mut header)?;
 
     // First two bytes is image width
     let width = buf_to_le_u32(&header[0..2])?;
     // Next two bytes is image height
     let height = buf_to_le_u32(&header[2..4])?;
     // Next 4 bytes is origin pixel coords
-    let _origin_x = buf_to_le_u32(&header[4..6])?;
-    let _origin_y = buf_to_le_u32(&header[6..8])?;
+    let origin_x = buf_to_le_i32(&header[4..6])?;
+    let origin_y = buf_to_le_i32(&header[6..8])?;
     // Last two pixels is palette ID
     let palette_id = buf_to_le_u32(&header[8..10])? as usize;
+    // Find palette in palettes
+    let palette = find_palette(palettes, palette_id)
+        .map_err(|error| error.at(asset.offset + 8))?;
+
+    // Image data can't be longer than the rest of asset
+    let length = width as u64 * height as u64;
+    let fits = match asset.length.checked_sub(HEADER_LEN as u64) {
+        Some(data_len) => length <= data_len,
+        None => false,
+    };
+    if !fits {
+        return Err(Error::new(ErrorKind::Truncated).at(asset.offset));
+    }
 
     // Read image data
-    let data_len = (asset.length as usize) - HEADER_LEN;
-    let mut image_data = vec![0u8;data_len];
-    res_file.read(&mut image_data)?;
+    let mut pixels = vec![0u8; length as usize];
+    read_buf(res_file, &mut pixels)?;
 
-    // Create output image
-    let mut img: RgbaImage = ImageBuffer::new(width, height);
-    // Find palette in palettes
-    let palette = &palettes[palette_id];
-    // First color of palette may be transparency pixel
-    // We bias to this interpretation
-    let transparency = &palette[0..3];
-
-    // Iterate over the coordinates and pixels of the image
-    for (x, y, pixel) in img.enumerate_pixels_mut() {
-        let src_pixel = (x + (y * width)) as usize;
-        let palette_color = image_data[src_pixel] as usize;
-        let color = [
-            palette[palette_color * 3],
-            palette[(palette_color * 3) + 1],
-            palette[(palette_color * 3) + 2],
-            255
-        ];
-
-        if &color[0..3] == transparency {
-            *pixel = Rgba([0, 0, 0, 0]);
-        } else {
-            *pixel = Rgba(color);
-        }
+    Ok(IndexedImage {
+        width,
+        height,
+        origin_x,
+        origin_y,
+        pixels,
+        palette_id: Some(palette_id),
+        palette,
+        // First color of palette may be transparency pixel
+        // We bias to this interpretation
+        transparency: Transparency::Color(0),
+    })
+}
+
+/// Encodes image with its hotspot and palette id as type 5 asset.
+pub fn encode_img(image: &IndexedImage) -> Result<Vec<u8>> {
+    // Header size has to describe stored pixels
+    let length = image.width as u64 * image.height as u64;
+    if image.pixels.len() as u64 != length {
+        let message = format!(
+            "image has {} pixels, expected {}x{}",
+            image.pixels.len(), image.width, image.height
+        );
+        return Err(Error::new(ErrorKind::InvalidArgument(message)));
+    }
+
+    let header = [
+        to_u16(image.width as i64, "width")?,
+        to_u16(image.height as i64, "height")?,
+        to_i16(image.origin_x, "origin x")?,
+        to_i16(image.origin_y, "origin y")?,
+        to_u16(image.palette_id.unwrap_or(0) as i64, "palette id")?,
+    ];
+
+    let mut data: Vec<u8> = Vec::with_capacity(HEADER_LEN + length as usize);
+    for value in &header {
+        data.extend_from_slice(value);
+    }
+    data.extend_from_slice(&image.pixels);
+
+    Ok(data)
+}
+
+pub fn read_img_info<R: Read + Seek>(
+    res_file: &mut R, asset: &Asset
+) -> Result<AssetInfo> {
+    res_file.seek(SeekFrom::Start(asset.offset))?;
+    let mut header = [0;HEADER_LEN];
+    read_buf(res_file, &mut header)?;
+
+    let frame = FrameInfo {
+        width: buf_to_le_u32(&header[0..2])?,
+        height: buf_to_le_u32(&header[2..4])?,
+        hotspot_x: buf_to_le_i32(&header[4..6])?,
+        hotspot_y: buf_to_le_i32(&header[6..8])?,
+    };
+    let palette_id = buf_to_le_u32(&header[8..10])? as usize;
+
+    Ok(AssetInfo {
+        palette_id: Some(palette_id),
+        unknown: None,
+        frames: vec![frame],
+    })
+}
+
+pub fn extract_img<R: Read + Seek>(
+    res_file: &mut R,
+    palettes: &[[u8; 768]],
+    asset: &Asset,
+    path: &mut PathBuf,
+    options: &ExtractOptions
+) -> Result<bool> {
+    // Add filename to path
+    path.push(&asset.name);
+    path.set_extension("PNG");
+
+    // If file already exists skip extraction
+    if path.is_file() {
+        return Ok(false);
     }
 
     // Save image file
-    img.save(path)?;
+    decode_img(res_file, palettes, asset)?.save_as(path, options.indexed)?;
 
     Ok(true)
-}
\ No newline at end of file
+}
+
+/// Returns little endian bytes of header value stored as `u16`.
+pub(super) fn to_u16(value: i64, name: &str) -> Result<[u8; 2]> {
+    if value < 0 || value > u16::MAX as i64 {
+        return Err(out_of_range(value, name));
+    }
+    Ok((value as u16).to_le_bytes())
+}
+
+/// Returns little endian bytes of header value stored as `i16`.
+pub(super) fn to_i16(value: i32, name: &str) -> Result<[u8; 2]> {
+    if value < i16::MIN as i32 || value > i16::MAX as i32 {
+        return Err(out_of_range(value as i64, name));
+    }
+    Ok((value as i16).to_le_bytes())
+}
+
+fn out_of_range(value: i64, name: &str) -> Error {
+    let message = format!("image {} {} doesn't fit 2 bytes", name, value);
+    Error::new(ErrorKind::InvalidArgument(message))
+}
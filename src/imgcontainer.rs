@@ -1,100 +1,326 @@
-use std::error::Error;
-use std::fs::{File, create_dir_all};
+use std::fs::create_dir_all;
 use std::io::{Read, Seek, SeekFrom};
 use std::path::PathBuf;
 use std::vec::Vec;
-use image::{ImageBuffer, Rgba, RgbaImage};
 
+use super::archive::ExtractOptions;
+use super::atlas::pack_frames;
+use super::canvas::{align_frames, ANCHOR_FILE};
 use super::directory::Asset;
-use super::utils::{buf_to_le_u32, buf_to_le_u64};
+use super::indexed::{IndexedImage, Transparency};
+use super::error::{Error, ErrorKind, Result};
+use super::img::{to_i16, to_u16};
+use super::manifest::{AssetInfo, FrameInfo};
+use super::palette::find_palette;
+use super::utils::{buf_to_le_i32, buf_to_le_u32, buf_to_le_u64, read_buf};
 
-pub fn extract_img_container(
-    res_file: &mut File,
-    palettes: &Vec<[u8; 768]>,
-    asset: &Asset,
-    path: &mut PathBuf
-) -> Result<bool, Box<dyn Error>> {
-    // Add filename to path
-    path.push(&asset.name);
-    // Create directory for asset images
-    if !path.is_dir() {
-        create_dir_all(&path)?;
-    }
+// Margin byte ending the row
+pub(super) const ROW_END: u8 = 255;
+// Longest transparent margin and color pixels run of single chunk
+const MAX_MARGIN: u32 = 254;
+const MAX_RUN: u32 = 255;
 
+pub fn decode_img_container<R: Read + Seek>(
+    res_file: &mut R,
+    palettes: &[[u8; 768]],
+    asset: &Asset
+) -> Result<Vec<IndexedImage>> {
     // Jump to asset start
     res_file.seek(SeekFrom::Start(asset.offset))?;
 
     // First two bytes of asset is number of images
     let mut images_count = [0;2];
-    res_file.read(&mut images_count)?;
+    read_buf(res_file, &mut images_count)?;
     let images_count = buf_to_le_u32(&images_count)? as usize;
 
     // Next two bytes of asset is palette id
-    let mut palette = [0;2];
-    res_file.read(&mut palette)?;
-    let palette = buf_to_le_u32(&palette)? as usize;
-    let palette = palettes[palette];
+    let mut palette_id = [0;2];
+    read_buf(res_file, &mut palette_id)?;
+    let palette_id = buf_to_le_u32(&palette_id)? as usize;
+    let palette = find_palette(palettes, palette_id)
+        .map_err(|error| error.at(asset.offset + 2))?;
 
     // Palette id is followed by list of image offsets
     // each offset is written with 4 bytes
     let mut images_offsets: Vec<u64> = Vec::new();
     while images_offsets.len() < images_count {
         let mut image_offset = [0;4];
-        res_file.read(&mut image_offset)?;
+        read_buf(res_file, &mut image_offset)?;
+        let image_offset = asset.offset + buf_to_le_u64(&image_offset)?;
+        images_offsets.push(image_offset);
+    }
+
+    // Decode every image
+    let mut images: Vec<IndexedImage> = Vec::new();
+    for image_offset in images_offsets {
+        let mut image = decode_img_from_container(
+            res_file, &palette, asset.offset, image_offset
+        )?;
+        image.palette_id = Some(palette_id);
+        images.push(image);
+    }
+
+    Ok(images)
+}
+
+pub fn read_img_container_info<R: Read + Seek>(
+    res_file: &mut R, asset: &Asset
+) -> Result<AssetInfo> {
+    res_file.seek(SeekFrom::Start(asset.offset))?;
+
+    // Images count and palette id are followed by images offsets
+    let mut header = [0;4];
+    read_buf(res_file, &mut header)?;
+    let images_count = buf_to_le_u32(&header[0..2])? as usize;
+    let palette_id = buf_to_le_u32(&header[2..4])? as usize;
+
+    let mut images_offsets: Vec<u64> = Vec::new();
+    while images_offsets.len() < images_count {
+        let mut image_offset = [0;4];
+        read_buf(res_file, &mut image_offset)?;
         let image_offset = asset.offset + buf_to_le_u64(&image_offset)?;
         images_offsets.push(image_offset);
     }
 
-    // Extract every image
-    for (i, image_offset) in images_offsets.into_iter().enumerate() {
+    let mut frames: Vec<FrameInfo> = Vec::new();
+    for image_offset in images_offsets {
+        frames.push(read_frame_info(res_file, image_offset)?);
+    }
+
+    Ok(AssetInfo { palette_id: Some(palette_id), unknown: None, frames })
+}
+
+pub fn extract_img_container<R: Read + Seek>(
+    res_file: &mut R,
+    palettes: &[[u8; 768]],
+    asset: &Asset,
+    path: &mut PathBuf,
+    options: &ExtractOptions
+) -> Result<bool> {
+    // Add filename to path
+    path.push(&asset.name);
+
+    let images = decode_img_container(res_file, palettes, asset)?;
+    save_frames(images, path, options)
+}
+
+/// Saves frames to directory as N.PNG, or to `path`.PNG atlas.
+///
+/// Atlas is described by `path`.json, in TexturePacker format. Returns
+/// `false` if atlas was already saved.
+pub(super) fn save_frames(
+    images: Vec<IndexedImage>, path: &mut PathBuf, options: &ExtractOptions
+) -> Result<bool> {
+    if options.atlas {
+        path.set_extension("PNG");
+        // If file already exists skip extraction
+        if path.is_file() {
+            return Ok(false);
+        }
+
+        let images = if options.align_frames {
+            align_frames(&images).1
+        } else {
+            images
+        };
+        let atlas = pack_frames(&images);
+        atlas.image.save_as(path, options.indexed)?;
+
+        let image_name = path.file_name().unwrap_or_default();
+        let descriptor = atlas.descriptor(
+            &images, &image_name.to_string_lossy(), options.indexed
+        );
+        descriptor.write(&path.with_extension("json"))?;
+        return Ok(true);
+    }
+
+    // Create directory for asset images
+    if !path.is_dir() {
+        create_dir_all(&path)?;
+    }
+
+    let images = if options.align_frames {
+        // Write anchor frames were aligned to next to them
+        let (canvas, images) = align_frames(&images);
+        canvas.write(&path.join(ANCHOR_FILE))?;
+        images
+    } else {
+        images
+    };
+
+    // Save every image
+    for (i, image) in images.into_iter().enumerate() {
         // Create final image path
         let mut img_path = path.to_path_buf();
         img_path.push(i.to_string());
         img_path.set_extension("PNG");
-        // If file doesnt exist, extract it
+        // If file doesnt exist, save it
         if !img_path.is_file() {
-            extract_img_from_container(
-                res_file, palette, asset.offset, image_offset, img_path
-            )?;
+            image.save_as(&img_path, options.indexed)?;
         }
     }
 
     Ok(true)
 }
 
-fn extract_img_from_container(
-    res_file: &mut File,
-    palette: [u8; 768],
+/// Encodes frames as type 2 container using palette with given id.
+///
+/// Every row is split into chunks of transparent margin and run of color
+/// pixels, as long as they fit single byte, and ends with 255 byte.
+pub fn encode_img_container(
+    frames: &[IndexedImage], palette_id: usize
+) -> Result<Vec<u8>> {
+    let mut data: Vec<u8> = Vec::new();
+    data.extend_from_slice(&to_u16(frames.len() as i64, "frames count")?);
+    data.extend_from_slice(&to_u16(palette_id as i64, "palette id")?);
+    encode_frames(&mut data, frames, true)?;
+
+    Ok(data)
+}
+
+/// Appends frame offsets list and frames, with or without color pixels.
+pub(super) fn encode_frames(
+    data: &mut Vec<u8>, frames: &[IndexedImage], with_pixels: bool
+) -> Result<()> {
+    for (i, frame) in frames.iter().enumerate() {
+        check_frame(frame, i, with_pixels)?;
+    }
+
+    let offsets_start = data.len();
+    data.resize(offsets_start + frames.len() * 4, 0);
+    for (i, frame) in frames.iter().enumerate() {
+        // Offsets are relative to asset start
+        let offset = (data.len() as u32).to_le_bytes();
+        let start = offsets_start + i * 4;
+        data[start..start + 4].copy_from_slice(&offset);
+        encode_frame(data, frame, with_pixels)?;
+    }
+
+    Ok(())
+}
+
+/// Checks that pixels and mask of frame cover its whole size.
+///
+/// Frames encoded without colors need pixels only if they have no mask.
+fn check_frame(
+    frame: &IndexedImage, i: usize, with_pixels: bool
+) -> Result<()> {
+    let mask = match &frame.transparency {
+        Transparency::Mask(mask) => Some(mask.len()),
+        _ => None,
+    };
+    let mut lengths: Vec<(&str, usize)> = Vec::new();
+    if let Some(mask) = mask {
+        lengths.push(("mask", mask));
+    }
+    // Without mask, opaque pixels are told by their colors
+    if with_pixels || mask.is_none() {
+        lengths.push(("pixels", frame.pixels.len()));
+    }
+
+    let length = frame.width as usize * frame.height as usize;
+    for (name, actual) in lengths {
+        if actual < length {
+            let message = format!(
+                "frame {} {} has {} pixels, expected {}x{}",
+                i, name, actual, frame.width, frame.height
+            );
+            return Err(Error::new(ErrorKind::InvalidArgument(message)));
+        }
+    }
+
+    Ok(())
+}
+
+fn encode_frame(
+    data: &mut Vec<u8>, frame: &IndexedImage, with_pixels: bool
+) -> Result<()> {
+    data.extend_from_slice(&to_u16(frame.width as i64, "width")?);
+    data.extend_from_slice(&to_u16(frame.height as i64, "height")?);
+    data.extend_from_slice(&to_i16(frame.origin_x, "center x")?);
+    data.extend_from_slice(&to_i16(frame.origin_y, "center y")?);
+
+    let offsets_start = data.len();
+    data.resize(offsets_start + frame.height as usize * 4, 0);
+    for y in 0..frame.height {
+        let offset = (data.len() as u32).to_le_bytes();
+        let start = offsets_start + y as usize * 4;
+        data[start..start + 4].copy_from_slice(&offset);
+        encode_row(data, frame, y, with_pixels);
+    }
+
+    Ok(())
+}
+
+fn encode_row(
+    data: &mut Vec<u8>, frame: &IndexedImage, y: u32, with_pixels: bool
+) {
+    let mut x = 0;
+    while x < frame.width {
+        // Count transparent pixels, trailing ones are left out
+        let margin_start = x;
+        while x < frame.width && !frame.is_opaque(x, y) {
+            x += 1;
+        }
+        if x == frame.width {
+            break;
+        }
+
+        // Margin too long for single chunk is split into empty chunks
+        let mut margin = x - margin_start;
+        while margin > MAX_MARGIN {
+            data.extend_from_slice(&[MAX_MARGIN as u8, 0]);
+            margin -= MAX_MARGIN;
+        }
+
+        // Longer runs continue in next chunk, with no margin
+        let run_start = x;
+        while x < frame.width && x - run_start < MAX_RUN
+            && frame.is_opaque(x, y) {
+            x += 1;
+        }
+        data.extend_from_slice(&[margin as u8, (x - run_start) as u8]);
+        if with_pixels {
+            data.extend((run_start..x).map(|x| frame.index(x, y)));
+        }
+    }
+    data.push(ROW_END);
+}
+
+fn decode_img_from_container<R: Read + Seek>(
+    res_file: &mut R,
+    palette: &[u8; 768],
     asset_offset: u64,
-    img_offset: u64,
-    path: PathBuf
-) -> Result<bool, Box<dyn Error>> {
+    img_offset: u64
+) -> Result<IndexedImage> {
     // Jump to image start
     res_file.seek(SeekFrom::Start(img_offset))?;
 
     // Read 8 bytes image header
     let mut header = [0;8];
-    res_file.read(&mut header)?;
+    read_buf(res_file, &mut header)?;
 
     // Deconstruct header into data
     let width = buf_to_le_u32(&header[0..2])?;
     let height = buf_to_le_u32(&header[2..4])?;
-    let _center_x = buf_to_le_u32(&header[4..6])?;
-    let _center_y = buf_to_le_u32(&header[6..8])?;
+    let center_x = buf_to_le_i32(&header[4..6])?;
+    let center_y = buf_to_le_i32(&header[6..8])?;
 
     // File is split into number of rows, each of varying length
     // Read rows offsets list
     let mut offsets: Vec<u64> = Vec::new();
     while offsets.len() < (height as usize) {
         let mut offset = [0;4];
-        res_file.read(&mut offset)?;
+        read_buf(res_file, &mut offset)?;
         let offset = asset_offset + buf_to_le_u64(&offset)?;
         offsets.push(offset);
     }
 
     // Create output image
-    let mut img: RgbaImage = ImageBuffer::new(width, height);
-    
+    let length = (width * height) as usize;
+    let mut pixels = vec![0u8; length];
+    let mut mask = vec![false; length];
+
     // Draw image row after row
     for (y, offset) in offsets.into_iter().enumerate() {
         // Jump to row start in image
@@ -103,39 +329,73 @@ fn extract_img_from_container(
         // Render row
         while x < width {
             // Row is split into chunks of varying length
-            // Chunk header is always two bytes long
-            let mut header = [0;2];
-            res_file.read(&mut header)?;
             // First byte is number of transparent pixels before color pixels
-            let margin = header[0] as u32;
+            let mut margin = [0;1];
+            read_buf(res_file, &mut margin)?;
             // If margin is 255, row end is reached
-            if margin == 255 {
+            if margin[0] == ROW_END {
                 break;
             }
+            let margin = margin[0] as u32;
 
             // Second byte is number of color pixels
-            let data_len = header[1] as usize;
+            let mut data_len = [0;1];
+            read_buf(res_file, &mut data_len)?;
+            let data_len = data_len[0] as usize;
             // Skip transparent pixels
             x += margin;
+            // Make sure pixels fit in the row
+            if x + data_len as u32 > width {
+                return Err(row_overflow_error(res_file, y, width));
+            }
             // Read color pixels
             let mut colors = vec![0u8;data_len];
-            res_file.read(&mut colors)?;
+            read_buf(res_file, &mut colors)?;
             for color in colors {
-                let color = color as usize;
-                let pixel = img.get_pixel_mut(x as u32, y as u32);
-                *pixel = Rgba([
-                    palette[color * 3],
-                    palette[(color * 3) + 1],
-                    palette[(color * 3) + 2],
-                    255
-                ]);
+                let pixel = (x + (y as u32 * width)) as usize;
+                pixels[pixel] = color;
+                mask[pixel] = true;
                 x += 1;
             }
         }
     }
-    
-    // Save image file
-    img.save(path)?;
 
-    Ok(true)
-}
\ No newline at end of file
+    Ok(IndexedImage {
+        width,
+        height,
+        origin_x: center_x,
+        origin_y: center_y,
+        pixels,
+        palette_id: None,
+        palette: *palette,
+        transparency: Transparency::Mask(mask),
+    })
+}
+
+/// Reads size and center of image stored in container.
+pub(super) fn read_frame_info<R: Read + Seek>(
+    res_file: &mut R, img_offset: u64
+) -> Result<FrameInfo> {
+    res_file.seek(SeekFrom::Start(img_offset))?;
+    let mut header = [0;8];
+    read_buf(res_file, &mut header)?;
+
+    Ok(FrameInfo {
+        width: buf_to_le_u32(&header[0..2])?,
+        height: buf_to_le_u32(&header[2..4])?,
+        hotspot_x: buf_to_le_i32(&header[4..6])?,
+        hotspot_y: buf_to_le_i32(&header[6..8])?,
+    })
+}
+
+pub(super) fn row_overflow_error<R: Read + Seek>(
+    res_file: &mut R, y: usize, width: u32
+) -> Error {
+    let message = format!("row {} is longer than image width {}", y, width);
+    let error = Error::new(ErrorKind::InvalidRle(message));
+    // Point to chunk header that overflowed the row
+    match res_file.stream_position() {
+        Ok(offset) => error.at(offset - 2),
+        Err(_) => error,
+    }
+}
@@ -1,31 +1,32 @@
-use std::error::Error;
-use std::fs::{File, create_dir_all};
 use std::io::{Read, Seek, SeekFrom};
 use std::path::PathBuf;
 use std::vec::Vec;
-use image::{ImageBuffer, Rgba, RgbaImage};
 
+use super::archive::ExtractOptions;
 use super::directory::Asset;
-use super::utils::{buf_to_le_u32, buf_to_le_u64};
-
-pub fn extract_img_mono_container(
-    res_file: &mut File,
-    asset: &Asset,
-    path: &mut PathBuf
-) -> Result<bool, Box<dyn Error>> {
-    // Add filename to path
-    path.push(&asset.name);
-    // Create directory for asset images
-    if !path.is_dir() {
-        create_dir_all(&path)?;
-    }
+use super::indexed::{IndexedImage, Transparency};
+use super::error::Result;
+use super::imgcontainer::{
+    encode_frames,
+    read_frame_info,
+    row_overflow_error,
+    save_frames,
+    ROW_END,
+};
+use super::img::to_u16;
+use super::manifest::{AssetInfo, FrameInfo};
+use super::utils::{buf_to_le_i32, buf_to_le_u32, buf_to_le_u64, read_buf};
 
+pub fn decode_img_mono_container<R: Read + Seek>(
+    res_file: &mut R,
+    asset: &Asset
+) -> Result<Vec<IndexedImage>> {
     // Jump to asset start
     res_file.seek(SeekFrom::Start(asset.offset))?;
 
     // First two bytes of asset is number of images
     let mut images_count = [0;2];
-    res_file.read(&mut images_count)?;
+    read_buf(res_file, &mut images_count)?;
     let images_count = buf_to_le_u32(&images_count)? as usize;
 
     // Number of images is followed by list of image offsets
@@ -33,60 +34,105 @@ pub fn extract_img_mono_container(
     let mut images_offsets: Vec<u64> = Vec::new();
     while images_offsets.len() < images_count {
         let mut image_offset = [0;4];
-        res_file.read(&mut image_offset)?;
+        read_buf(res_file, &mut image_offset)?;
         let image_offset = asset.offset + buf_to_le_u64(&image_offset)?;
         images_offsets.push(image_offset);
     }
 
-    // Extract every image
-    for (i, image_offset) in images_offsets.into_iter().enumerate() {
-        // Create final image path
-        let mut img_path = path.to_path_buf();
-        img_path.push(i.to_string());
-        img_path.set_extension("PNG");
-        // If file doesnt exist, extract it
-        if !img_path.is_file() {
-            extract_img_from_container(
-                res_file, asset.offset, image_offset, img_path
-            )?;
-        }
+    // Decode every image
+    let mut images: Vec<IndexedImage> = Vec::new();
+    for image_offset in images_offsets {
+        images.push(
+            decode_img_from_container(res_file, asset.offset, image_offset)?
+        );
+    }
+
+    Ok(images)
+}
+
+pub fn read_img_mono_container_info<R: Read + Seek>(
+    res_file: &mut R, asset: &Asset
+) -> Result<AssetInfo> {
+    res_file.seek(SeekFrom::Start(asset.offset))?;
+
+    // Images count is followed by images offsets
+    let mut images_count = [0;2];
+    read_buf(res_file, &mut images_count)?;
+    let images_count = buf_to_le_u32(&images_count)? as usize;
+
+    let mut images_offsets: Vec<u64> = Vec::new();
+    while images_offsets.len() < images_count {
+        let mut image_offset = [0;4];
+        read_buf(res_file, &mut image_offset)?;
+        let image_offset = asset.offset + buf_to_le_u64(&image_offset)?;
+        images_offsets.push(image_offset);
+    }
+
+    let mut frames: Vec<FrameInfo> = Vec::new();
+    for image_offset in images_offsets {
+        frames.push(read_frame_info(res_file, image_offset)?);
     }
 
-    Ok(true)
+    Ok(AssetInfo { palette_id: None, unknown: None, frames })
 }
 
-fn extract_img_from_container(
-    res_file: &mut File,
+pub fn extract_img_mono_container<R: Read + Seek>(
+    res_file: &mut R,
+    asset: &Asset,
+    path: &mut PathBuf,
+    options: &ExtractOptions
+) -> Result<bool> {
+    // Add filename to path
+    path.push(&asset.name);
+
+    let images = decode_img_mono_container(res_file, asset)?;
+    save_frames(images, path, options)
+}
+
+/// Encodes shapes of frames as type 3 container, colors are left out.
+///
+/// Rows are split into chunks the same way sprite container rows are,
+/// chunks just have no color pixels.
+pub fn encode_img_mono_container(frames: &[IndexedImage]) -> Result<Vec<u8>> {
+    let mut data: Vec<u8> = Vec::new();
+    data.extend_from_slice(&to_u16(frames.len() as i64, "frames count")?);
+    encode_frames(&mut data, frames, false)?;
+
+    Ok(data)
+}
+
+fn decode_img_from_container<R: Read + Seek>(
+    res_file: &mut R,
     asset_offset: u64,
-    img_offset: u64,
-    path: PathBuf
-) -> Result<bool, Box<dyn Error>> {
+    img_offset: u64
+) -> Result<IndexedImage> {
     // Jump to image start
     res_file.seek(SeekFrom::Start(img_offset))?;
 
     // Read 8 bytes image header
     let mut header = [0;8];
-    res_file.read(&mut header)?;
+    read_buf(res_file, &mut header)?;
 
     // Deconstruct header into data
     let width = buf_to_le_u32(&header[0..2])?;
     let height = buf_to_le_u32(&header[2..4])?;
-    let _center_x = buf_to_le_u32(&header[4..6])?;
-    let _center_y = buf_to_le_u32(&header[6..8])?;
+    let center_x = buf_to_le_i32(&header[4..6])?;
+    let center_y = buf_to_le_i32(&header[6..8])?;
 
     // File is split into number of rows, each of varying length
     // Read rows offsets list
     let mut offsets: Vec<u64> = Vec::new();
     while offsets.len() < (height as usize) {
         let mut offset = [0;4];
-        res_file.read(&mut offset)?;
+        read_buf(res_file, &mut offset)?;
         let offset = asset_offset + buf_to_le_u64(&offset)?;
         offsets.push(offset);
     }
 
-    // Create output image
-    let mut img: RgbaImage = ImageBuffer::new(width, height);
-    
+    // Mono images have no colors, only shape stored in mask
+    let length = (width * height) as usize;
+    let mut mask = vec![false; length];
+
     // Draw image row after row
     for (y, offset) in offsets.into_iter().enumerate() {
         // Jump to row start in image
@@ -95,32 +141,43 @@ fn extract_img_from_container(
         // Render row
         while x < width {
             // Row is split into chunks of varying length
-            // Chunk header is always two bytes long
-            let mut header = [0;2];
-            res_file.read(&mut header)?;
             // First byte is number of transparent pixels before color pixels
-            let margin = header[0] as u32;
+            let mut margin = [0;1];
+            read_buf(res_file, &mut margin)?;
             // If margin is 255, row end is reached
-            if margin == 255 {
+            if margin[0] == ROW_END {
                 break;
             }
+            let margin = margin[0] as u32;
 
             // Second byte is number of color pixels
-            let mut data_len = header[1] as usize;
+            let mut data_len = [0;1];
+            read_buf(res_file, &mut data_len)?;
+            let mut data_len = data_len[0] as usize;
             // Skip transparent pixels
             x += margin;
+            // Make sure pixels fit in the row
+            if x + data_len as u32 > width {
+                return Err(row_overflow_error(res_file, y, width));
+            }
             // Draw color pixels
             while data_len > 0 {
-                let pixel = img.get_pixel_mut(x as u32, y as u32);
-                *pixel = Rgba([0, 0, 0, 255]);
+                mask[(x + (y as u32 * width)) as usize] = true;
                 x += 1;
                 data_len -= 1;
             }
         }
     }
-    
-    // Save image file
-    img.save(path)?;
 
-    Ok(true)
-}
\ No newline at end of file
+    Ok(IndexedImage {
+        width,
+        height,
+        origin_x: center_x,
+        origin_y: center_y,
+        pixels: vec![0u8; length],
+        palette_id: None,
+        // Mono pixels are drawn black
+        palette: [0; 768],
+        transparency: Transparency::Mask(mask),
+    })
+}
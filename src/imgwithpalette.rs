@@ -1,30 +1,34 @@
-use std::error::Error;
-use std::fs::File;
-use std::io::{Read, Seek, SeekFrom, Write};
+use std::collections::VecDeque;
+use std::io::{Read, Seek, SeekFrom};
 use std::path::PathBuf;
 use std::vec::Vec;
-use image::{ImageBuffer, Rgb, RgbImage};
 
+use super::archive::ExtractOptions;
 use super::directory::Asset;
-use super::utils::{buf_to_le_i32, buf_to_le_u32};
+use super::indexed::{IndexedImage, Transparency};
+use super::error::{Error, ErrorKind, Result};
+use super::img::to_u16;
+use super::manifest::{AssetInfo, FrameInfo};
+use super::utils::{buf_to_le_i32, buf_to_le_u32, read_buf};
 
 // Asset header: 4 bytes + 2 bytes + 2 bytes + 3*256 bytes of palette
 const UNKNOWN_LEN: usize = 4;
 const PALETTE_LEN: usize = 3 * 256;
 const HEADER_LEN: usize = 2 + 2 + PALETTE_LEN;
+// Longest literal and repeat stored in single chunk
+const MAX_LITERAL: usize = i16::MAX as usize;
+const MAX_REPEAT: usize = i16::MAX as usize + 1;
 
-pub fn extract_img_with_palette(
-    res_file: &mut File, asset: &Asset, path: &mut PathBuf
-) -> Result<bool, Box<dyn Error>> {
-    // Add filename to path
-    path.push(&asset.name);
-    path.set_extension("PNG");
-
-    // If file already exists skip extraction
-    if path.is_file() {
-        return Ok(false);
-    }
+/// Compressed chunk starting at given pixel, with number of pixels it has.
+#[derive(Clone, Copy)]
+enum Chunk {
+    Literal(usize),
+    Repeat(usize),
+}
 
+pub fn decode_img_with_palette<R: Read + Seek>(
+    res_file: &mut R, asset: &Asset
+) -> Result<IndexedImage> {
     // Jump asset offset in file
     // its offset from dict header + 4 bytes of trash
     let offset = asset.offset + (UNKNOWN_LEN as u64);
@@ -32,63 +36,239 @@ pub fn extract_img_with_palette(
 
     // Read 2 + 2 + (3 * 256) bytes of asset header
     let mut header = [0;HEADER_LEN];
-    res_file.read(&mut header)?;
+    read_buf(res_file, &mut header)?;
 
     // First two bytes is image width
     let width = buf_to_le_u32(&header[0..2])?;
     // Next two bytes is image height
     let height = buf_to_le_u32(&header[2..4])?;
     // Next 3*256 bytes is image palette
-    let palette = &header[4..(PALETTE_LEN + 4)];
+    let mut palette = [0u8; PALETTE_LEN];
+    palette.copy_from_slice(&header[4..(PALETTE_LEN + 4)]);
 
     // Decompress image data
-    let data_len = (asset.length as usize) - HEADER_LEN - UNKNOWN_LEN;
-    let image_data = decompress_img_data(res_file, data_len)?;
-
-    // Create image from data
-    let mut img: RgbImage = ImageBuffer::new(width, height);
-    for (x, y, pixel) in img.enumerate_pixels_mut() {
-        let src_pixel = (x + (y * width)) as usize;
-        let palette_color = (image_data[src_pixel] as usize) * 3;
-        let red = palette[palette_color];
-        let green = palette[palette_color + 1];
-        let blue = palette[palette_color + 2];
-        *pixel = Rgb([red, green, blue]);
+    let data_len = (asset.length as usize)
+        .checked_sub(HEADER_LEN + UNKNOWN_LEN)
+        .ok_or_else(|| Error::new(ErrorKind::Truncated).at(asset.offset))?;
+    let mut pixels = decompress_img_data(res_file, data_len)?;
+    let length = (width * height) as usize;
+    if pixels.len() < length {
+        let message = format!(
+            "{} pixels decompressed for {}x{} image",
+            pixels.len(), width, height
+        );
+        let error = Error::new(ErrorKind::InvalidRle(message));
+        let data_offset = (UNKNOWN_LEN + HEADER_LEN) as u64;
+        return Err(error.at(asset.offset + data_offset));
+    }
+    // Pixels past the image aren't shown
+    pixels.truncate(length);
+
+    Ok(IndexedImage {
+        width,
+        height,
+        origin_x: 0,
+        origin_y: 0,
+        pixels,
+        palette_id: None,
+        palette,
+        transparency: Transparency::None,
+    })
+}
+
+/// Encodes image with its palette as type 1 asset, starting with `unknown`.
+///
+/// Pixels are compressed into the fewest bytes literal and repeat chunks
+/// can store them in.
+pub fn encode_img_with_palette(
+    image: &IndexedImage, unknown: [u8; 4]
+) -> Result<Vec<u8>> {
+    let length = (image.width * image.height) as usize;
+    if image.pixels.len() < length {
+        let message = format!(
+            "image has {} pixels, expected {}x{}",
+            image.pixels.len(), image.width, image.height
+        );
+        return Err(Error::new(ErrorKind::InvalidArgument(message)));
+    }
+
+    let mut data: Vec<u8> = Vec::with_capacity(UNKNOWN_LEN + HEADER_LEN);
+    data.extend_from_slice(&unknown);
+    data.extend_from_slice(&to_u16(image.width as i64, "width")?);
+    data.extend_from_slice(&to_u16(image.height as i64, "height")?);
+    data.extend_from_slice(&image.palette);
+    compress_img_data(&mut data, &image.pixels[..length]);
+
+    Ok(data)
+}
+
+pub fn read_img_with_palette_info<R: Read + Seek>(
+    res_file: &mut R, asset: &Asset
+) -> Result<AssetInfo> {
+    // Only unknown bytes, width and height are needed
+    res_file.seek(SeekFrom::Start(asset.offset))?;
+    let mut header = [0;UNKNOWN_LEN + 4];
+    read_buf(res_file, &mut header)?;
+
+    let mut unknown = [0u8; UNKNOWN_LEN];
+    unknown.copy_from_slice(&header[0..UNKNOWN_LEN]);
+    let frame = FrameInfo {
+        width: buf_to_le_u32(&header[4..6])?,
+        height: buf_to_le_u32(&header[6..8])?,
+        hotspot_x: 0,
+        hotspot_y: 0,
+    };
+
+    Ok(AssetInfo {
+        palette_id: None,
+        unknown: Some(unknown),
+        frames: vec![frame],
+    })
+}
+
+pub fn extract_img_with_palette<R: Read + Seek>(
+    res_file: &mut R,
+    asset: &Asset,
+    path: &mut PathBuf,
+    options: &ExtractOptions
+) -> Result<bool> {
+    // Add filename to path
+    path.push(&asset.name);
+    path.set_extension("PNG");
+
+    // If file already exists skip extraction
+    if path.is_file() {
+        return Ok(false);
     }
 
-    img.save(path)?;
+    let image = decode_img_with_palette(res_file, asset)?;
+    image.save_as(path, options.indexed)?;
 
     Ok(true)
 }
 
-fn decompress_img_data(
-    res_file: &mut File, data_len: usize
-) -> Result<Vec<u8>, Box<dyn Error>> {
+fn decompress_img_data<R: Read + Seek>(
+    res_file: &mut R, data_len: usize
+) -> Result<Vec<u8>> {
+    let data_offset = res_file.stream_position()?;
     let mut data = vec![0u8; data_len];
-    res_file.read(&mut data)?;
+    read_buf(res_file, &mut data)?;
 
     let mut position: usize = 0;
     let mut unpacked_data: Vec<u8> = Vec::new();
 
     while position < data_len {
+        let chunk_offset = data_offset + position as u64;
         // Every compressed chunk starts with 2-bytes word
+        if position + 2 > data_len {
+            return Err(Error::new(ErrorKind::Truncated).at(chunk_offset));
+        }
         let sword = buf_to_le_i32(&data[position..position+2])?;
         let start = position + 2;
         // If sword is positive, its number of uncompressed bytes
+        // otherwise its number of repeats of next byte
+        let end = if sword > 0 { start + (sword as usize) } else { start + 1 };
+        if end > data_len {
+            let message = format!("chunk of {} bytes exceeds image data", sword);
+            let error = Error::new(ErrorKind::InvalidRle(message));
+            return Err(error.at(chunk_offset));
+        }
+
         if sword > 0 {
-            let end = start + (sword as usize);
-            unpacked_data.write(&data[start..end])?;
-            position = end;
+            unpacked_data.extend_from_slice(&data[start..end]);
         } else {
-            let mut repeat = sword.abs();
-            let end = start + 1;
-            while repeat > 0 {
-                unpacked_data.write(&data[start..end])?;
-                repeat -= 1;
-            }
-            position = end;
+            let repeat = sword.unsigned_abs() as usize;
+            unpacked_data.resize(unpacked_data.len() + repeat, data[start]);
         }
+        position = end;
     }
 
     Ok(unpacked_data)
-}
\ No newline at end of file
+}
+
+fn compress_img_data(data: &mut Vec<u8>, pixels: &[u8]) {
+    let chunks = plan_chunks(pixels);
+
+    let mut position = 0;
+    while position < pixels.len() {
+        // Positive word is number of literal bytes following it,
+        // negative or zero is number of repeats of single next byte
+        position = match chunks[position] {
+            Chunk::Literal(length) => {
+                let end = position + length;
+                data.extend_from_slice(&(length as i16).to_le_bytes());
+                data.extend_from_slice(&pixels[position..end]);
+                end
+            },
+            Chunk::Repeat(length) => {
+                let sword = -(length as i32) as i16;
+                data.extend_from_slice(&sword.to_le_bytes());
+                data.push(pixels[position]);
+                position + length
+            },
+        };
+    }
+}
+
+/// Picks chunk starting at every pixel, so that chunks from the first pixel
+/// take fewest bytes.
+///
+/// Literal of n pixels takes 2 + n bytes and repeat takes 3 bytes, so cost
+/// of pixels from position on is cheapest chunk plus cost of pixels after
+/// it. Cheapest next positions are kept in sliding windows, going from the
+/// last pixel back.
+fn plan_chunks(pixels: &[u8]) -> Vec<Chunk> {
+    let length = pixels.len();
+    let mut costs = vec![0usize; length + 1];
+    let mut chunks = vec![Chunk::Literal(1); length];
+    // Positions literal or repeat can end at, with their costs
+    let mut literal_ends: VecDeque<(usize, usize)> = VecDeque::new();
+    let mut repeat_ends: VecDeque<(usize, usize)> = VecDeque::new();
+
+    for position in (0..length).rev() {
+        let next = position + 1;
+
+        // Literal cost is 2 + (end - position) + costs[end]
+        push_candidate(&mut literal_ends, next, next + costs[next]);
+        while literal_ends[0].0 > position + MAX_LITERAL {
+            literal_ends.pop_front();
+        }
+
+        // Repeat can only end within run of equal pixels
+        if next == length || pixels[next] != pixels[position] {
+            repeat_ends.clear();
+        }
+        push_candidate(&mut repeat_ends, next, costs[next]);
+        while repeat_ends[0].0 > position + MAX_REPEAT {
+            repeat_ends.pop_front();
+        }
+
+        let (literal_end, literal_cost) = literal_ends[0];
+        let literal_cost = 2 + literal_cost - position;
+        let (repeat_end, repeat_cost) = repeat_ends[0];
+        let repeat_cost = 3 + repeat_cost;
+
+        // Repeats are used only when they save bytes
+        if repeat_cost < literal_cost {
+            costs[position] = repeat_cost;
+            chunks[position] = Chunk::Repeat(repeat_end - position);
+        } else {
+            costs[position] = literal_cost;
+            chunks[position] = Chunk::Literal(literal_end - position);
+        }
+    }
+
+    chunks
+}
+
+/// Adds chunk end to window, keeping costs increasing from the front.
+///
+/// Ends added earlier make longer chunks, so they win equal costs.
+fn push_candidate(
+    window: &mut VecDeque<(usize, usize)>, end: usize, cost: usize
+) {
+    while matches!(window.back(), Some(&(_, back_cost)) if back_cost > cost) {
+        window.pop_back();
+    }
+    window.push_back((end, cost));
+}
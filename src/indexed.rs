@@ -0,0 +1,275 @@
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use image::{
+    ColorType, GrayImage, ImageBuffer, Rgb, RgbImage, Rgba, RgbaImage
+};
+
+use super::error::Result;
+use super::pngfile::write_png;
+use super::quantize::{quantize, remap};
+
+// Pixels with lower alpha are transparent when image is read
+const ALPHA_THRESHOLD: u8 = 128;
+
+/// Describes which pixels of indexed image are transparent.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Transparency {
+    /// Every pixel is opaque
+    None,
+    /// Pixels with same color as given palette index are transparent
+    Color(u8),
+    /// Per-pixel flags, `true` marks opaque pixel
+    Mask(Vec<bool>),
+}
+
+/// Decoded image storing palette indices instead of colors.
+#[derive(Clone, Debug)]
+pub struct IndexedImage {
+    pub width: u32,
+    pub height: u32,
+    /// Hotspot (origin or center) coordinates from image header
+    pub origin_x: i32,
+    pub origin_y: i32,
+    /// Palette index for every pixel, row after row
+    pub pixels: Vec<u8>,
+    /// Id of archive palette, if image is using one
+    pub palette_id: Option<usize>,
+    /// 3 * 256 bytes of RGB colors
+    pub palette: [u8; 768],
+    pub transparency: Transparency,
+}
+
+impl IndexedImage {
+    /// Maps colors of RGBA image to nearest palette colors.
+    ///
+    /// Transparent pixels (alpha below 128) get palette index 0, which
+    /// decoders of archive images treat as transparent.
+    pub fn from_rgba(img: &RgbaImage, palette: &[u8; 768]) -> IndexedImage {
+        let colors: Vec<[u8; 3]> = img.pixels()
+            .map(|pixel| [pixel[0], pixel[1], pixel[2]])
+            .collect();
+        let pixels = remap(&colors, palette).into_iter()
+            .zip(img.pixels())
+            .map(|(index, pixel)| {
+                if pixel[3] < ALPHA_THRESHOLD { 0 } else { index }
+            })
+            .collect();
+
+        IndexedImage {
+            width: img.width(),
+            height: img.height(),
+            origin_x: 0,
+            origin_y: 0,
+            pixels,
+            palette_id: None,
+            palette: *palette,
+            transparency: Transparency::Color(0),
+        }
+    }
+
+    /// Reads image file, mapping its colors like `from_rgba` does.
+    pub fn open(path: &Path, palette: &[u8; 768]) -> Result<IndexedImage> {
+        let img = image::open(path)?.to_rgba();
+        Ok(IndexedImage::from_rgba(&img, palette))
+    }
+
+    /// Builds opaque image with palette fitting its colors.
+    ///
+    /// `palette` is kept if it has every color of image, otherwise palette
+    /// is made from image colors, reduced with median cut if needed.
+    pub fn from_rgb(img: &RgbImage, palette: &[u8; 768]) -> IndexedImage {
+        let colors: Vec<[u8; 3]> = img.pixels()
+            .map(|pixel| [pixel[0], pixel[1], pixel[2]])
+            .collect();
+        let known: HashSet<&[u8]> = palette.chunks(3).collect();
+        let is_known = colors.iter().all(|color| known.contains(&color[..]));
+        let (palette, pixels) = if is_known {
+            (*palette, remap(&colors, palette))
+        } else {
+            quantize(&colors)
+        };
+
+        IndexedImage {
+            width: img.width(),
+            height: img.height(),
+            origin_x: 0,
+            origin_y: 0,
+            pixels,
+            palette_id: None,
+            palette,
+            transparency: Transparency::None,
+        }
+    }
+
+    /// Reads image file with its own palette, see `from_rgb`.
+    pub fn open_opaque(
+        path: &Path, palette: &[u8; 768]
+    ) -> Result<IndexedImage> {
+        let img = image::open(path)?.to_rgb();
+        Ok(IndexedImage::from_rgb(&img, palette))
+    }
+
+    /// Builds shadow frame from alpha channel of RGBA image.
+    ///
+    /// Pixels with alpha of at least `threshold` are opaque and black, like
+    /// pixels of decoded shadow containers.
+    pub fn mask_from_alpha(img: &RgbaImage, threshold: u8) -> IndexedImage {
+        let mask = img.pixels().map(|pixel| pixel[3] >= threshold);
+        IndexedImage::from_mask(img.width(), img.height(), mask.collect())
+    }
+
+    /// Builds shadow frame from 1-bit or grayscale mask.
+    ///
+    /// Pixels darker than `threshold` are opaque, so black pixels of 1-bit
+    /// mask become shadow.
+    pub fn mask_from_luma(img: &GrayImage, threshold: u8) -> IndexedImage {
+        let mask = img.pixels().map(|pixel| pixel[0] < threshold);
+        IndexedImage::from_mask(img.width(), img.height(), mask.collect())
+    }
+
+    /// Reads shadow frame from image file.
+    ///
+    /// Images with alpha channel are masked by alpha, other ones by
+    /// brightness, see `mask_from_alpha` and `mask_from_luma`.
+    pub fn open_mask(path: &Path, threshold: u8) -> Result<IndexedImage> {
+        let img = image::open(path)?;
+        match img.color() {
+            ColorType::GrayA(_) | ColorType::RGBA(_) | ColorType::BGRA(_) => {
+                Ok(IndexedImage::mask_from_alpha(&img.to_rgba(), threshold))
+            },
+            _ => Ok(IndexedImage::mask_from_luma(&img.to_luma(), threshold)),
+        }
+    }
+
+    fn from_mask(width: u32, height: u32, mask: Vec<bool>) -> IndexedImage {
+        IndexedImage {
+            width,
+            height,
+            origin_x: 0,
+            origin_y: 0,
+            pixels: vec![0u8; mask.len()],
+            palette_id: None,
+            palette: [0; 768],
+            transparency: Transparency::Mask(mask),
+        }
+    }
+
+    /// Returns palette index of pixel at given coordinates.
+    pub fn index(&self, x: u32, y: u32) -> u8 {
+        self.pixels[(x + (y * self.width)) as usize]
+    }
+
+    /// Returns RGB color of given palette index.
+    pub fn color(&self, index: u8) -> [u8; 3] {
+        let index = index as usize * 3;
+        [
+            self.palette[index],
+            self.palette[index + 1],
+            self.palette[index + 2],
+        ]
+    }
+
+    /// Checks if pixel at given coordinates is opaque.
+    pub fn is_opaque(&self, x: u32, y: u32) -> bool {
+        match &self.transparency {
+            Transparency::None => true,
+            Transparency::Color(index) => {
+                self.color(self.index(x, y)) != self.color(*index)
+            },
+            Transparency::Mask(mask) => mask[(x + (y * self.width)) as usize],
+        }
+    }
+
+    /// Converts image to RGB, ignoring transparency.
+    pub fn to_rgb(&self) -> RgbImage {
+        let mut img: RgbImage = ImageBuffer::new(self.width, self.height);
+        for (x, y, pixel) in img.enumerate_pixels_mut() {
+            *pixel = Rgb(self.color(self.index(x, y)));
+        }
+        img
+    }
+
+    /// Converts image to RGBA, transparent pixels are zeroed.
+    pub fn to_rgba(&self) -> RgbaImage {
+        let mut img: RgbaImage = ImageBuffer::new(self.width, self.height);
+        for (x, y, pixel) in img.enumerate_pixels_mut() {
+            if self.is_opaque(x, y) {
+                let [red, green, blue] = self.color(self.index(x, y));
+                *pixel = Rgba([red, green, blue, 255]);
+            } else {
+                *pixel = Rgba([0, 0, 0, 0]);
+            }
+        }
+        img
+    }
+
+    /// Saves image in format picked from path's extension.
+    ///
+    /// Images without transparency are saved without alpha channel.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        match self.transparency {
+            Transparency::None => self.to_rgb().save(path)?,
+            _ => self.to_rgba().save(path)?,
+        }
+        Ok(())
+    }
+
+    /// Saves image as 8-bit PNG with palette, keeping pixel indices.
+    ///
+    /// Transparent index is stored in tRNS chunk.
+    pub fn save_indexed(&self, path: &Path) -> Result<()> {
+        let (pixels, transparent) = self.indexed_pixels();
+        let mut file = BufWriter::new(File::create(path)?);
+        write_png(
+            &mut file,
+            self.width,
+            self.height,
+            &self.palette,
+            transparent,
+            &pixels
+        )
+    }
+
+    /// Saves image as indexed PNG or in format picked from extension.
+    pub fn save_as(&self, path: &Path, indexed: bool) -> Result<()> {
+        if indexed {
+            self.save_indexed(path)
+        } else {
+            self.save(path)
+        }
+    }
+
+    /// Returns pixels with single index for transparent ones, and that index.
+    fn indexed_pixels(&self) -> (Cow<'_, [u8]>, Option<u8>) {
+        let mask = match &self.transparency {
+            Transparency::None => return (Cow::Borrowed(&self.pixels), None),
+            Transparency::Color(index) => {
+                return (Cow::Borrowed(&self.pixels), Some(*index));
+            },
+            Transparency::Mask(mask) => mask,
+        };
+
+        // Masked pixels need index no opaque pixel uses
+        let mut used = [false; 256];
+        for (index, opaque) in self.pixels.iter().zip(mask.iter()) {
+            if *opaque {
+                used[*index as usize] = true;
+            }
+        }
+        // Prefer index 0, transparent pixels are decoded with it.
+        // If every index is used, transparent pixels take index 0 too
+        let transparent = if !used[0] {
+            0
+        } else {
+            (0..=255u8).rev().find(|index| !used[*index as usize]).unwrap_or(0)
+        };
+
+        let pixels = self.pixels.iter().zip(mask.iter())
+            .map(|(index, opaque)| if *opaque { *index } else { transparent })
+            .collect();
+        (Cow::Owned(pixels), Some(transparent))
+    }
+}
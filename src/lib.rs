@@ -0,0 +1,42 @@
+//! Reading game assets from Mechanized Assault & Exploration 2 ("M.A.X 2").
+//!
+//! Entry point is the `Archive` type, which opens `RES0` packages such as
+//! `MAX2.RES` and `MAX2.CAF`, lists assets stored in them and reads them.
+
+pub mod acm;
+pub mod animation;
+pub mod archive;
+pub mod atlas;
+pub mod canvas;
+pub mod directory;
+pub mod error;
+pub mod filter;
+pub mod img;
+pub mod imgcontainer;
+pub mod imgmonocontainer;
+pub mod imgwithpalette;
+pub mod indexed;
+pub mod manifest;
+pub mod mesh;
+pub mod palette;
+pub mod pngfile;
+pub mod quantize;
+pub mod raw;
+pub mod report;
+pub mod resbuilder;
+pub mod resfile;
+pub mod text;
+pub mod tiled;
+pub mod typemap;
+pub mod wld;
+pub mod wldbuilder;
+pub mod world;
+
+mod utils;
+
+pub use archive::{Archive, ExtractOptions};
+pub use directory::{Asset, AssetKind, Directory};
+pub use error::{Error, ErrorKind, Result};
+pub use filter::{AssetFilter, TypeSelector};
+pub use indexed::{IndexedImage, Transparency};
+pub use manifest::{AssetInfo, FrameInfo, Manifest};
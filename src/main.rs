@@ -1,277 +1,1276 @@
+use std::collections::HashMap;
 use std::env::current_dir;
 use std::error::Error;
-use std::fs::{File, create_dir_all};
-use std::io;
-use std::iter::Iterator;
-use std::io::{Read, Seek, SeekFrom};
-use std::path::PathBuf;
-use std::vec::Vec;
-use glob::glob;
-
-mod acm;
-mod directory;
-mod img;
-mod imgcontainer;
-mod imgmonocontainer;
-mod imgwithpalette;
-mod palette;
-mod raw;
-mod resfile;
-mod text;
-mod utils;
-mod wld;
-
-use acm::extract_acm;
-use directory::{Asset, Directory, get_directory};
-use img::extract_img;
-use imgcontainer::extract_img_container;
-use imgmonocontainer::extract_img_mono_container;
-use imgwithpalette::extract_img_with_palette;
-use palette::render_palette;
-use raw::extract_raw;
-use resfile::open_res_file;
-use text::extract_txt;
-use utils::buf_to_le_u32;
-use wld::extract_wld;
-
-// const ASSET_METADATA: u32 = 0; - Not used in M.A.X 2
-const ASSET_IMG_WITH_PALETTE: u32 = 1;
-const ASSET_IMG_CONTAINER: u32 = 2;
-const ASSET_IMG_MONO_CONTAINER: u32 = 3;
-const ASSET_STR: u32 = 4;
-const ASSET_IMG: u32 = 5;
-const ASSET_TXT: u32 = 7;
-const ASSET_ACM: u32 = 8;
-
-fn main() -> Result<(), Box<dyn Error>> {
-    let mut max2_res = match open_res_file("MAX2.RES") {
-        Ok(file) => file,
-        Err(error) => {
-            panic!("Failed to open MAX2.RES: {:?}", error)
-        },
-    };
+use std::fs::{create_dir_all, read, read_dir};
+use std::path::{Path, PathBuf};
+use std::process;
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand, crate_version};
+use glob::{glob, Pattern};
 
-    let mut max2_caf = match open_res_file("MAX2.CAF") {
-        Ok(file) => file,
-        Err(error) => {
-            panic!("Failed to open MAX2.CAF: {:?}", error)
-        },
-    };
+use max2::{
+    Archive,
+    Asset,
+    AssetFilter,
+    AssetKind,
+    ErrorKind,
+    ExtractOptions,
+    IndexedImage,
+    Manifest,
+    TypeSelector,
+};
+use max2::animation::{AnimationFormat, AnimationOptions};
+use max2::canvas::ANCHOR_FILE;
+use max2::palette::{find_palette, read_palette_image, render_palette};
+use max2::report::Report;
+use max2::resbuilder::ArchiveBuilder;
+use max2::tiled::TiledFormat;
+use max2::typemap::TypeColors;
+use max2::wld::extract_wld;
+use max2::wldbuilder::{
+    build_world, read_heightmap, read_map_image, read_typemap
+};
+use max2::world::{SectorType, World, DEFAULT_UNKNOWN, SECTOR_EDGE};
 
-    let dst_path = match get_dst_path() {
-        Ok(dst_path) => dst_path,
+const DEFAULT_ARCHIVES: [&str; 2] = ["MAX2.RES", "MAX2.CAF"];
+const DEFAULT_DST_DIR: &str = "extracted";
+const REPORT_FILE: &str = "report.txt";
+const MANIFEST_FILE: &str = "manifest.json";
+const PALETTE_DIR: &str = "palette";
+// Alpha or brightness separating shadow pixels from empty ones
+const DEFAULT_THRESHOLD: u8 = 128;
+
+/// Paths and settings shared by all subcommands
+struct Options {
+    game_dir: PathBuf,
+    dst_path: PathBuf,
+    /// Name asset directories by type number instead of kind name
+    numeric_dirs: bool,
+    /// How assets are written, set by `extract` subcommand
+    extract: ExtractOptions,
+}
+
+fn main() {
+    let matches = build_cli().get_matches();
+    let options = match get_options(&matches) {
+        Ok(options) => options,
         Err(error) => {
-            panic!("Failed to find current directory: {:?}", error)
+            exit_with_error("Failed to find current directory", &*error)
         },
     };
 
-    match create_dir_all(&dst_path) {
-        Ok(_) => (),
-        Err(error) => {
-            panic!("Failed to create \"extracted\" directory: {:?}", error)
+    match matches.subcommand() {
+        ("list", Some(matches)) => list(&options, matches),
+        ("info", Some(matches)) => info(&options, matches),
+        ("verify", Some(matches)) => {
+            let mut report = start_extraction(&options);
+            verify(&options, matches, &mut report);
+            finish(&options, &report);
+        },
+        ("extract", Some(matches)) => {
+            let mut report = start_extraction(&options);
+            let mut manifest = Manifest::new();
+            extract(&options, matches, &mut manifest, &mut report);
+            write_manifest(&options, &manifest, &mut report);
+            finish(&options, &report);
+        },
+        ("extract-wld", Some(matches)) => {
+            let mut report = start_extraction(&options);
+            extract_wlds(&options, matches, &mut report);
+            finish(&options, &report);
         },
+        ("animate", Some(matches)) => {
+            let mut report = start_extraction(&options);
+            animate(&options, matches, &mut report);
+            finish(&options, &report);
+        },
+        ("palettes", Some(matches)) => {
+            let mut report = start_extraction(&options);
+            palettes(&options, matches, &mut report);
+            finish(&options, &report);
+        },
+        ("build-wld", Some(matches)) => {
+            if let Err(error) = build_wld(&options, matches) {
+                exit_with_error("Failed to build WLD", &error)
+            }
+        },
+        ("replace", Some(matches)) => {
+            if let Err(error) = replace(&options, matches) {
+                exit_with_error("Failed to replace asset", &error)
+            }
+        },
+        ("build-res", Some(matches)) => {
+            if let Err(error) = build_res(&options, matches) {
+                exit_with_error("Failed to build RES", &error)
+            }
+        },
+        _ => {
+            // Without subcommand extract everything from game directory
+            let mut report = start_extraction(&options);
+            let mut manifest = Manifest::new();
+            let matches = ArgMatches::default();
+            extract(&options, &matches, &mut manifest, &mut report);
+            write_manifest(&options, &manifest, &mut report);
+            extract_wlds(&options, &ArgMatches::default(), &mut report);
+            finish(&options, &report);
+        },
+    }
+}
+
+fn build_cli() -> App<'static, 'static> {
+    let archives = Arg::with_name("archives")
+        .value_name("ARCHIVE")
+        .multiple(true)
+        .help("RES archives to process [default: MAX2.RES MAX2.CAF]");
+    let indexed = Arg::with_name("indexed")
+        .long("indexed")
+        .help("Saves images as 8-bit PNG with palette, keeping its indices");
+    let type_color = Arg::with_name("type-color")
+        .long("type-color")
+        .value_name("TYPE=COLOR")
+        .multiple(true)
+        .number_of_values(1)
+        .help(
+            "Sets typemap color of sector type (name or number), \
+             eg. water=#0000ff"
+        );
+    let filters = [
+        Arg::with_name("name")
+            .long("name")
+            .value_name("GLOB")
+            .multiple(true)
+            .number_of_values(1)
+            .help("Selects assets with name matching glob pattern"),
+        Arg::with_name("regex")
+            .long("regex")
+            .value_name("REGEX")
+            .multiple(true)
+            .number_of_values(1)
+            .help("Selects assets with name matching regular expression"),
+        Arg::with_name("type")
+            .long("type")
+            .value_name("TYPE")
+            .multiple(true)
+            .number_of_values(1)
+            .help("Selects assets of type (number, kind name or \"unknown\")"),
+        Arg::with_name("exclude-type")
+            .long("exclude-type")
+            .value_name("TYPE")
+            .multiple(true)
+            .number_of_values(1)
+            .help("Skips assets of type (number, kind name or \"unknown\")"),
+        Arg::with_name("source")
+            .long("source")
+            .value_name("GLOB")
+            .multiple(true)
+            .number_of_values(1)
+            .help("Selects archives with file name matching glob pattern"),
+    ];
+
+    App::new("max2-extractor")
+        .version(crate_version!())
+        .about("Extracts game assets from M.A.X 2")
+        .after_help(
+            "Relative archive and WLD paths are resolved against game \
+             directory, other inputs against current directory. \
+             Without subcommand, every archive and WLD file is extracted."
+        )
+        .setting(AppSettings::VersionlessSubcommands)
+        .arg(
+            Arg::with_name("game-dir")
+                .short("g")
+                .long("game-dir")
+                .value_name("DIR")
+                .global(true)
+                .help("Directory with game files [default: current directory]")
+        )
+        .arg(
+            Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .value_name("DIR")
+                .global(true)
+                .help("Directory to extract files to [default: ./extracted]")
+        )
+        .arg(
+            Arg::with_name("numeric-dirs")
+                .long("numeric-dirs")
+                .global(true)
+                .help("Names asset directories by type number, eg. \"5\"")
+        )
+        .subcommand(
+            SubCommand::with_name("list")
+                .about("Lists assets stored in archives")
+                .arg(archives.clone())
+                .args(&filters)
+        )
+        .subcommand(
+            SubCommand::with_name("verify")
+                .about(
+                    "Checks that decoded assets are encoded back to \
+                     stored data"
+                )
+                .arg(archives.clone())
+                .args(&filters)
+        )
+        .subcommand(
+            SubCommand::with_name("info")
+                .about("Shows archives summary")
+                .arg(archives.clone())
+        )
+        .subcommand(
+            SubCommand::with_name("extract")
+                .about("Extracts assets and palettes from archives")
+                .arg(archives.clone())
+                .args(&filters)
+                .arg(indexed.clone())
+                .arg(
+                    Arg::with_name("align-frames")
+                        .long("align-frames")
+                        .help(
+                            "Places container frames on common canvas, \
+                             aligned by their hotspots"
+                        )
+                )
+                .arg(
+                    Arg::with_name("atlas")
+                        .long("atlas")
+                        .help(
+                            "Packs container frames into single sprite sheet \
+                             with TexturePacker JSON descriptor"
+                        )
+                )
+                .arg(
+                    Arg::with_name("raw")
+                        .long("raw")
+                        .help(
+                            "Saves assets as stored in archive, without \
+                             decoding them"
+                        )
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("animate")
+                .about("Saves sprite containers as animated GIF or APNG")
+                .arg(archives.clone())
+                .args(&filters)
+                .arg(
+                    Arg::with_name("shadow")
+                        .long("shadow")
+                        .value_name("NAME")
+                        .help(
+                            "Shadow container drawn under sprite, \
+                             \"{}\" is replaced with sprite name"
+                        )
+                )
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .value_name("FORMAT")
+                        .possible_values(&["gif", "apng"])
+                        .default_value("gif")
+                        .help("Animation format")
+                )
+                .arg(
+                    Arg::with_name("delay")
+                        .long("delay")
+                        .value_name("MS")
+                        .default_value("100")
+                        .help("Time every frame is shown for")
+                )
+                .arg(
+                    Arg::with_name("frames")
+                        .long("frames")
+                        .value_name("START-END")
+                        .help("Animates only frames in range [default: all]")
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("extract-wld")
+                .about("Extracts maps from WLD files")
+                .arg(
+                    Arg::with_name("wlds")
+                        .value_name("WLD")
+                        .multiple(true)
+                        .help("WLD files to extract [default: *.WLD]")
+                )
+                .arg(indexed)
+                .arg(
+                    Arg::with_name("r16")
+                        .long("r16")
+                        .help(
+                            "Saves heightmap also as raw 16-bit little endian \
+                             .r16 file"
+                        )
+                )
+                .arg(
+                    Arg::with_name("mesh")
+                        .long("mesh")
+                        .help(
+                            "Saves terrain as OBJ and glTF meshes, textured \
+                             with full.png"
+                        )
+                )
+                .arg(
+                    Arg::with_name("tiled")
+                        .long("tiled")
+                        .value_name("FORMAT")
+                        .possible_values(&["tmx", "json"])
+                        .help(
+                            "Saves map for Tiled editor, with deduplicated \
+                             tileset"
+                        )
+                )
+                .arg(type_color.clone())
+        )
+        .subcommand(
+            SubCommand::with_name("palettes")
+                .about("Extracts palettes from archives")
+                .arg(archives)
+        )
+        .subcommand(
+            SubCommand::with_name("build-wld")
+                .about("Builds WLD map from image of whole map")
+                .arg(
+                    Arg::with_name("map")
+                        .value_name("MAP")
+                        .required(true)
+                        .help(
+                            "Map image, every 32x32 pixels square becomes \
+                             sector"
+                        )
+                )
+                .arg(
+                    Arg::with_name("wld")
+                        .value_name("WLD")
+                        .required(true)
+                        .help("Name of WLD file written to output directory")
+                )
+                .arg(
+                    Arg::with_name("heightmap")
+                        .long("heightmap")
+                        .value_name("FILE")
+                        .help(
+                            "Grayscale PNG or .r16 file with vertex heights \
+                             [default: flat map]"
+                        )
+                )
+                .arg(
+                    Arg::with_name("typemap")
+                        .long("typemap")
+                        .value_name("FILE")
+                        .help(
+                            "CSV or image with sector types \
+                             [default: grass]"
+                        )
+                )
+                .arg(
+                    Arg::with_name("palette")
+                        .long("palette")
+                        .value_name("FILE")
+                        .help(
+                            "16x16 palette image colors are mapped to \
+                             [default: made from map colors]"
+                        )
+                )
+                .arg(
+                    Arg::with_name("from")
+                        .long("from")
+                        .value_name("WLD")
+                        .help("Existing map whose unknown header bytes are kept")
+                )
+                .arg(type_color)
+        )
+        .subcommand(
+            SubCommand::with_name("replace")
+                .about("Replaces single asset of archive in place")
+                .arg(
+                    Arg::with_name("archive")
+                        .value_name("ARCHIVE")
+                        .required(true)
+                        .help("Archive changed in place")
+                )
+                .arg(
+                    Arg::with_name("name")
+                        .value_name("NAME")
+                        .required(true)
+                        .help("Name of replaced asset")
+                )
+                .arg(
+                    Arg::with_name("file")
+                        .value_name("FILE")
+                        .required(true)
+                        .help(
+                            "Asset data as stored in archive, PNG image \
+                             encoded to format of image asset, or directory \
+                             with container frames (0.PNG, 1.PNG...)"
+                        )
+                )
+                .arg(
+                    Arg::with_name("threshold")
+                        .long("threshold")
+                        .value_name("0-255")
+                        .help(
+                            "Shadow frames are pixels with alpha of at least \
+                             threshold, or darker than it in images without \
+                             alpha [default: 128]"
+                        )
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("build-res")
+                .about("Builds RES archive from assets and palettes")
+                .arg(
+                    Arg::with_name("res")
+                        .value_name("RES")
+                        .required(true)
+                        .help(
+                            "Name of RES file written to output directory"
+                        )
+                )
+                .arg(
+                    Arg::with_name("assets")
+                        .value_name("DIR")
+                        .required_unless("from")
+                        .help(
+                            "Directory with assets extracted with --raw, \
+                             grouped by kind, and palette images"
+                        )
+                )
+                .arg(
+                    Arg::with_name("from")
+                        .long("from")
+                        .value_name("ARCHIVE")
+                        .help(
+                            "Archive whose assets and palettes are copied, \
+                             then replaced by ones from DIR"
+                        )
+                )
+        )
+}
+
+fn get_options(matches: &ArgMatches) -> Result<Options, Box<dyn Error>> {
+    let cwd = current_dir()?;
+    let game_dir = match matches.value_of_os("game-dir") {
+        Some(game_dir) => cwd.join(game_dir),
+        None => cwd.clone(),
+    };
+    let dst_path = match matches.value_of_os("output") {
+        Some(dst_path) => cwd.join(dst_path),
+        None => cwd.join(DEFAULT_DST_DIR),
     };
 
-    match extract_max2_res(&dst_path, &mut max2_res) {
-        Ok(_) => (),
-        Err(error) => {
-            panic!("Failed to extract MAX2.RES: {:?}", error)
+    let numeric_dirs = matches.is_present("numeric-dirs");
+    let extract = match matches.subcommand() {
+        ("extract", Some(matches)) | ("extract-wld", Some(matches)) => {
+            match get_extract_options(matches) {
+                Ok(extract) => extract,
+                Err(error) => {
+                    exit_with_error("Failed to parse extract options", &error)
+                },
+            }
         },
+        _ => ExtractOptions::default(),
     };
 
-    match extract_max2_caf(&dst_path, &mut max2_caf) {
-        Ok(_) => (),
-        Err(error) => {
-            panic!("Failed to extract MAX2.CAF: {:?}", error)
+    Ok(Options { game_dir, dst_path, numeric_dirs, extract })
+}
+
+/// Returns archive paths given to subcommand, or default ones
+fn get_archives(options: &Options, matches: &ArgMatches) -> Vec<PathBuf> {
+    match matches.values_of_os("archives") {
+        Some(archives) => {
+            archives.map(|path| options.game_dir.join(path)).collect()
         },
+        None => {
+            DEFAULT_ARCHIVES.iter()
+                .map(|name| options.game_dir.join(name))
+                .collect()
+        },
+    }
+}
+
+/// Builds asset filter from subcommand's options
+fn get_filter(matches: &ArgMatches) -> max2::Result<AssetFilter> {
+    let mut filter = AssetFilter::new();
+    for pattern in matches.values_of("name").into_iter().flatten() {
+        filter = filter.name_glob(pattern)?;
+    }
+    for pattern in matches.values_of("regex").into_iter().flatten() {
+        filter = filter.name_regex(pattern)?;
+    }
+    for type_ in matches.values_of("type").into_iter().flatten() {
+        filter = filter.include_type(TypeSelector::parse(type_)?);
+    }
+    for type_ in matches.values_of("exclude-type").into_iter().flatten() {
+        filter = filter.exclude_type(TypeSelector::parse(type_)?);
+    }
+    for pattern in matches.values_of("source").into_iter().flatten() {
+        filter = filter.source(pattern)?;
+    }
+
+    Ok(filter)
+}
+
+/// Builds extraction settings from subcommand's options
+fn get_extract_options(
+    matches: &ArgMatches
+) -> max2::Result<ExtractOptions> {
+    let type_colors = get_type_colors(matches)?;
+    Ok(ExtractOptions {
+        align_frames: matches.is_present("align-frames"),
+        atlas: matches.is_present("atlas"),
+        raw: matches.is_present("raw"),
+        indexed: matches.is_present("indexed"),
+        raw_heightmap: matches.is_present("r16"),
+        mesh: matches.is_present("mesh"),
+        tiled: matches.value_of("tiled").and_then(TiledFormat::parse),
+        type_colors,
+    })
+}
+
+/// Builds typemap colors from subcommand's options
+fn get_type_colors(matches: &ArgMatches) -> max2::Result<TypeColors> {
+    let mut type_colors = TypeColors::default();
+    for value in matches.values_of("type-color").into_iter().flatten() {
+        let (type_, color) = TypeColors::parse_assignment(value)?;
+        type_colors.set(type_, color);
+    }
+    Ok(type_colors)
+}
+
+/// Builds animation settings from `animate` subcommand's options
+fn get_animation_options(
+    matches: &ArgMatches
+) -> Result<AnimationOptions, String> {
+    let mut options = AnimationOptions::default();
+    if let Some(format) = matches.value_of("format") {
+        options.format = AnimationFormat::parse(format)
+            .ok_or_else(|| format!("\"{}\" is not a format", format))?;
+    }
+    if let Some(delay) = matches.value_of("delay") {
+        options.delay = delay.parse()
+            .map_err(|_| format!("\"{}\" is not a delay", delay))?;
+    }
+    if let Some(frames) = matches.value_of("frames") {
+        // Single frame number or inclusive range, eg. "8-15"
+        let error = || format!("\"{}\" is not a frames range", frames);
+        let mut bounds = frames.splitn(2, '-').map(|bound| bound.parse());
+        let start = bounds.next().and_then(|b| b.ok()).ok_or_else(error)?;
+        let end = match bounds.next() {
+            Some(end) => end.map_err(|_| error())?,
+            None => start,
+        };
+        options.frames = Some(start..=end);
+    }
+
+    Ok(options)
+}
+
+fn get_file_name(path: &Path) -> String {
+    match path.file_name() {
+        Some(file_name) => file_name.to_string_lossy().into_owned(),
+        None => path.to_string_lossy().into_owned(),
+    }
+}
+
+fn exit_with_error(message: &str, error: &dyn Error) -> ! {
+    eprintln!("{}: {}", message, error);
+    process::exit(1)
+}
+
+fn list(options: &Options, matches: &ArgMatches) {
+    let filter = match get_filter(matches) {
+        Ok(filter) => filter,
+        Err(error) => exit_with_error("Failed to parse filters", &error),
     };
 
-    match extract_wlds(&dst_path) {
-        Ok(_) => (),
-        Err(error) => {
-            panic!("Failed to extract *.WLD: {:?}", error)
-        },
+    for path in get_archives(options, matches) {
+        let name = get_file_name(&path);
+        if !filter.matches_source(&name) {
+            continue;
+        }
+
+        let archive = match Archive::open(&path) {
+            Ok(archive) => archive,
+            Err(error) => {
+                exit_with_error(&format!("Failed to open {}", name), &error)
+            },
+        };
+
+        println!("{}:", name);
+        println!(
+            "{:>10} {:>10} {:>4}  {:18}  Name",
+            "Offset", "Length", "Type", "Kind"
+        );
+        for asset in archive.find_assets(&filter) {
+            println!(
+                "{:>10} {:>10} {:>4}  {:18}  {}",
+                asset.offset,
+                asset.length,
+                asset.kind.type_id(),
+                asset.kind.name(),
+                asset.name
+            );
+        }
+    }
+}
+
+fn verify(options: &Options, matches: &ArgMatches, report: &mut Report) {
+    let filter = match get_filter(matches) {
+        Ok(filter) => filter,
+        Err(error) => exit_with_error("Failed to parse filters", &error),
     };
 
-    Ok(())
+    for path in get_archives(options, matches) {
+        let name = get_file_name(&path);
+        if !filter.matches_source(&name) {
+            continue;
+        }
+
+        let mut archive = match Archive::open(&path) {
+            Ok(archive) => archive,
+            Err(error) => {
+                report.add_error(&name, &error);
+                continue;
+            },
+        };
+
+        println!("Verifying {}...", name);
+        let assets: Vec<Asset> = archive.find_assets(&filter).into_iter()
+            .filter(|asset| asset.kind.is_encodable())
+            .collect();
+        let mut exact = 0;
+        for asset in &assets {
+            let data = archive.reencode(asset)
+                .and_then(|data| Ok((data, archive.read_stored(asset)?)));
+            match data {
+                Ok((data, stored)) if data == stored => exact += 1,
+                Ok((data, stored)) => {
+                    let message = describe_difference(&data, &stored);
+                    report.add_failure(&name, Some(&asset.name), &message);
+                },
+                Err(error) => report.add_error(&name, &error),
+            }
+        }
+        println!(
+            "{} of {} assets encoded back to stored data", exact, assets.len()
+        );
+    }
 }
 
-fn get_dst_path() -> Result<PathBuf, io::Error> {
-    let mut path = current_dir()?;
-    path.push("extracted");
-    Ok(path)
+/// Describes where encoded data starts to differ from stored data
+fn describe_difference(data: &[u8], stored: &[u8]) -> String {
+    let offset = data.iter().zip(stored.iter())
+        .position(|(byte, stored)| byte != stored)
+        .unwrap_or_else(|| data.len().min(stored.len()));
+    format!(
+        "encoded data differs at byte {} ({} bytes, stored {})",
+        offset, data.len(), stored.len()
+    )
 }
 
-fn extract_max2_res(
-    dst_path: &PathBuf, res_file: &mut File
-) -> Result<(), Box<dyn Error>> {
-    println!("Extracting MAX2.RES...");
+fn info(options: &Options, matches: &ArgMatches) {
+    for path in get_archives(options, matches) {
+        let name = get_file_name(&path);
+        let archive = match Archive::open(&path) {
+            Ok(archive) => archive,
+            Err(error) => {
+                exit_with_error(&format!("Failed to open {}", name), &error)
+            },
+        };
 
-    let directory = get_directory(res_file)?;
-    let palettes = get_palettes(res_file, &directory)?;
+        let directory = archive.directory();
+        println!("{}:", name);
+        println!("  Path: {}", path.display());
+        println!(
+            "  Directory: offset {}, length {}",
+            directory.offset, directory.length
+        );
+        println!("  Palettes: {}", archive.palettes().len());
+        println!("  Assets: {}", directory.assets.len());
 
-    let mut dst_path = dst_path.to_path_buf();
-    dst_path.push("res");
+        // Count assets of every kind
+        let mut kinds: Vec<(AssetKind, usize)> = Vec::new();
+        for asset in &directory.assets {
+            match kinds.iter_mut().find(|(kind, _)| *kind == asset.kind) {
+                Some((_, count)) => *count += 1,
+                None => kinds.push((asset.kind, 1)),
+            }
+        }
+        kinds.sort();
+        for (kind, count) in kinds {
+            println!("    {} (type {}): {}", kind, kind.type_id(), count);
+        }
+    }
+}
 
-    extract_max2_res_palettes(&dst_path, &palettes)?;
-    extract_assets(&dst_path, res_file, &palettes, &directory.assets)?;
+fn start_extraction(options: &Options) -> Report {
+    if let Err(error) = create_dir_all(&options.dst_path) {
+        let message = format!(
+            "Failed to create \"{}\" directory", options.dst_path.display()
+        );
+        exit_with_error(&message, &error)
+    }
 
-    Ok(())
+    Report::new()
+}
+
+fn write_manifest(
+    options: &Options, manifest: &Manifest, report: &mut Report
+) {
+    let mut manifest_path = options.dst_path.to_path_buf();
+    manifest_path.push(MANIFEST_FILE);
+    if let Err(error) = manifest.write(&manifest_path) {
+        report.add_failure(MANIFEST_FILE, None, &error.to_string());
+    }
+}
+
+fn finish(options: &Options, report: &Report) {
+    let mut report_path = options.dst_path.to_path_buf();
+    report_path.push(REPORT_FILE);
+    if let Err(error) = report.write(&report_path) {
+        eprintln!("Failed to write {}: {}", report_path.display(), error);
+    }
+
+    if report.has_failures() {
+        eprintln!();
+        eprint!("{}", report);
+        process::exit(1);
+    }
+    if report.has_warnings() {
+        eprintln!();
+        eprint!("{}", report);
+    }
+}
+
+/// Returns directory archive is extracted to, eg. "res" for MAX2.RES
+fn get_archive_dst_path(options: &Options, path: &Path) -> PathBuf {
+    let dirname = match path.extension() {
+        Some(extension) => extension.to_string_lossy().to_lowercase(),
+        None => get_file_name(path).to_lowercase(),
+    };
+
+    let mut dst_path = options.dst_path.to_path_buf();
+    dst_path.push(dirname);
+    dst_path
+}
+
+fn extract(
+    options: &Options,
+    matches: &ArgMatches,
+    manifest: &mut Manifest,
+    report: &mut Report
+) {
+    let filter = match get_filter(matches) {
+        Ok(filter) => filter,
+        Err(error) => exit_with_error("Failed to parse filters", &error),
+    };
+
+    for path in get_archives(options, matches) {
+        let name = get_file_name(&path);
+        if !filter.matches_source(&name) {
+            continue;
+        }
+
+        match Archive::open(&path) {
+            Ok(mut archive) => {
+                println!("Extracting {}...", name);
+                let dst_path = get_archive_dst_path(options, &path);
+                extract_palettes(&name, &dst_path, archive.palettes(), report);
+                let assets = archive.find_assets(&filter);
+                extract_assets(
+                    options,
+                    &name,
+                    &dst_path,
+                    &mut archive,
+                    &assets,
+                    manifest,
+                    report
+                );
+            },
+            Err(error) => report.add_error(&name, &error),
+        };
+    }
 }
 
-fn extract_max2_res_palettes(
-    dst_path: &PathBuf, palettes: &Vec<[u8; 768]>
-) -> Result<(), Box<dyn Error>> {
+fn palettes(options: &Options, matches: &ArgMatches, report: &mut Report) {
+    for path in get_archives(options, matches) {
+        let name = get_file_name(&path);
+        match Archive::open(&path) {
+            Ok(archive) => {
+                let dst_path = get_archive_dst_path(options, &path);
+                extract_palettes(&name, &dst_path, archive.palettes(), report);
+            },
+            Err(error) => report.add_error(&name, &error),
+        };
+    }
+}
+
+fn extract_palettes(
+    source: &str, dst_path: &Path, palettes: &[[u8; 768]], report: &mut Report
+) {
+    if palettes.is_empty() {
+        return;
+    }
+
     println!("Extracting {} palettes...", palettes.len());
 
     let mut dst_path = dst_path.to_path_buf();
-    dst_path.push("palette");
-    create_dir_all(&dst_path)?;
+    dst_path.push(PALETTE_DIR);
+    if let Err(error) = create_dir_all(&dst_path) {
+        report.add_failure(source, None, &error.to_string());
+        return;
+    }
 
-    for (i, &palette) in palettes.into_iter().enumerate() {
+    for (i, palette) in palettes.iter().enumerate() {
         let mut palette_path = dst_path.to_path_buf();
         palette_path.push(i.to_string().as_str());
         palette_path.set_extension("PNG");
-        if render_palette(&palette_path, &palette)? {
-            println!("Extracted palette #{}", i);
+        match render_palette(&palette_path, palette) {
+            Ok(true) => println!("Extracted palette #{}", i),
+            Ok(false) => (),
+            Err(error) => {
+                let name = format!("palette #{}", i);
+                report.add_failure(source, Some(&name), &error.to_string());
+            },
         }
     }
+}
 
-    Ok(())
+fn extract_assets(
+    options: &Options,
+    source: &str,
+    dst_path: &Path,
+    archive: &mut Archive,
+    assets: &[Asset],
+    manifest: &mut Manifest,
+    report: &mut Report
+) {
+    for asset in assets {
+        // Assert that directory for kind exists
+        let mut dst_type_path = dst_path.to_path_buf();
+        if options.numeric_dirs {
+            dst_type_path.push(asset.kind.type_id().to_string());
+        } else {
+            dst_type_path.push(asset.kind.name());
+        }
+        if let Err(error) = create_dir_all(&dst_type_path) {
+            report.add_failure(source, Some(&asset.name), &error.to_string());
+            continue;
+        }
+
+        // Extract asset using type based algorithm
+        match archive.extract(asset, &mut dst_type_path, &options.extract) {
+            Ok(true) => println!("Extracted {}", asset.name),
+            Ok(false) => (),
+            Err(error) => {
+                eprintln!("Failed to extract {}", asset.name);
+                report.add_error(source, &error);
+                continue;
+            },
+        }
+
+        // Describe extracted asset in manifest
+        match archive.describe(asset) {
+            Ok(info) => {
+                let frames_count = info.frames.len();
+                let files = get_extracted_files(&dst_type_path, frames_count);
+                manifest.add(source, asset, info, &files, &options.dst_path);
+            },
+            Err(error) => report.add_error(source, &error),
+        }
+    }
 }
 
-fn extract_max2_caf(
-    dst_path: &PathBuf, res_file: &mut File
-) -> Result<(), Box<dyn Error>> {
-    println!("Extracting MAX2.CAF...");
+/// Returns files asset was extracted to, given path `Archive::extract` left
+fn get_extracted_files(path: &Path, frames_count: usize) -> Vec<PathBuf> {
+    // Containers are extracted to directory with image for every frame
+    if path.is_dir() {
+        let mut files: Vec<PathBuf> = (0..frames_count)
+            .map(|i| path.join(format!("{}.PNG", i)))
+            .collect();
+        // Aligned frames come with anchor sidecar
+        let anchor_path = path.join(ANCHOR_FILE);
+        if anchor_path.is_file() {
+            files.push(anchor_path);
+        }
+        return files;
+    }
 
-    let directory = get_directory(res_file)?;
+    // Atlas is described by JSON file of same name
+    let descriptor_path = path.with_extension("json");
+    if descriptor_path.is_file() {
+        return vec![path.to_path_buf(), descriptor_path];
+    }
 
-    let mut dst_path = dst_path.to_path_buf();
-    dst_path.push("caf");
+    vec![path.to_path_buf()]
+}
 
-    for asset in directory.assets {
-        // Assert that directory for type exists
-        let mut dst_type_path = dst_path.to_path_buf();
-        dst_type_path.push(asset.type_.to_string());
-        create_dir_all(&dst_type_path)?;
+fn animate(options: &Options, matches: &ArgMatches, report: &mut Report) {
+    let filter = match get_filter(matches) {
+        Ok(filter) => filter,
+        Err(error) => exit_with_error("Failed to parse filters", &error),
+    };
+    let animation_options = match get_animation_options(matches) {
+        Ok(animation_options) => animation_options,
+        Err(message) => {
+            eprintln!("Failed to parse animation options: {}", message);
+            process::exit(1)
+        },
+    };
+    let shadow_template = matches.value_of("shadow");
 
-        // Extract asset using type based algorithm
-        match asset.type_ {
-            ASSET_STR | ASSET_TXT => {
-                if extract_txt(res_file, &asset, &mut dst_type_path)? {
-                    println!("Extracted {}", asset.name)
-                }
+    for path in get_archives(options, matches) {
+        let name = get_file_name(&path);
+        if !filter.matches_source(&name) {
+            continue;
+        }
+
+        let mut archive = match Archive::open(&path) {
+            Ok(archive) => archive,
+            Err(error) => {
+                report.add_error(&name, &error);
+                continue;
             },
-            ASSET_ACM => {
-                if extract_acm(res_file, &asset, &mut dst_type_path)? {
-                    println!("Extracted {}", asset.name)
+        };
+
+        let mut dst_path = get_archive_dst_path(options, &path);
+        dst_path.push("animation");
+        if let Err(error) = create_dir_all(&dst_path) {
+            report.add_failure(&name, None, &error.to_string());
+            continue;
+        }
+
+        println!("Animating {}...", name);
+        // Only sprite containers can be animated
+        let sprites: Vec<Asset> = archive.find_assets(&filter).into_iter()
+            .filter(|asset| asset.kind == AssetKind::SpriteContainer)
+            .collect();
+        for sprite in sprites {
+            // Animate without shadow if template doesn't match any asset
+            let shadow = shadow_template.and_then(|template| {
+                let shadow_name = template.replace("{}", &sprite.name);
+                let shadow = archive.asset(&shadow_name).cloned();
+                if shadow.is_none() {
+                    eprintln!("No {} shadow for {}", shadow_name, sprite.name);
                 }
-            },
-            _ => {
-                if extract_raw(res_file, &asset, &mut dst_type_path)? {
-                    println!("Extracted {}", asset.name)
+                shadow
+            });
+
+            let mut animation_path = dst_path.to_path_buf();
+            animation_path.push(&sprite.name);
+            animation_path.set_extension(animation_options.format.extension());
+
+            let animation = archive.animate(
+                &sprite, shadow.as_ref(), &animation_options
+            );
+            let result = animation.and_then(|animation| {
+                animation.save(&animation_path, &animation_options)
+            });
+            match result {
+                Ok(()) => println!("Animated {}", sprite.name),
+                Err(error) => {
+                    eprintln!("Failed to animate {}", sprite.name);
+                    report.add_error(&name, &error.with_asset(&sprite));
+                },
+            }
+        }
+    }
+}
+
+/// Returns WLD paths given to subcommand, or ones found in game directory
+fn get_wlds(
+    options: &Options, matches: &ArgMatches
+) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    if let Some(wlds) = matches.values_of_os("wlds") {
+        return Ok(wlds.map(|path| options.game_dir.join(path)).collect());
+    }
+
+    let game_dir = options.game_dir.to_string_lossy();
+    let pattern = format!("{}/*.WLD", Pattern::escape(&game_dir));
+    let mut wlds: Vec<PathBuf> = Vec::new();
+    for wld_path in glob(&pattern)? {
+        let wld_path = wld_path?;
+        if wld_path.is_file() {
+            wlds.push(wld_path);
+        }
+    }
+
+    Ok(wlds)
+}
+
+fn extract_wlds(options: &Options, matches: &ArgMatches, report: &mut Report) {
+    let wlds = match get_wlds(options, matches) {
+        Ok(wlds) => wlds,
+        Err(error) => {
+            report.add_failure("*.WLD", None, &error.to_string());
+            return;
+        },
+    };
+
+    // Assert that directory for type exists
+    let mut dst_type_path = options.dst_path.to_path_buf();
+    dst_type_path.push("wld");
+    if let Err(error) = create_dir_all(&dst_type_path) {
+        report.add_failure("*.WLD", None, &error.to_string());
+        return;
+    }
+
+    for wld_path in wlds {
+        let wld_name = get_file_name(&wld_path);
+        println!("Extracting {}...", wld_name);
+        match extract_wld(&wld_path, &dst_type_path, &options.extract) {
+            Ok(warnings) => {
+                for warning in warnings {
+                    report.add_warning(&wld_name, &warning);
                 }
             },
+            Err(error) => {
+                eprintln!("Failed to extract {}", wld_name);
+                report.add_error(&wld_name, &error);
+            },
         }
     }
+}
+
+fn build_wld(options: &Options, matches: &ArgMatches) -> max2::Result<()> {
+    let type_colors = get_type_colors(matches)?;
+    let map = read_map_image(Path::new(matches.value_of_os("map").unwrap()))?;
+    let width = map.width() / SECTOR_EDGE;
+    let height = map.height() / SECTOR_EDGE;
+
+    let palette = match matches.value_of_os("palette") {
+        Some(path) => Some(read_palette_image(Path::new(path))?),
+        None => None,
+    };
+    let heights = match matches.value_of_os("heightmap") {
+        Some(path) => read_heightmap(Path::new(path), width, height)?,
+        None => vec![0; ((width + 1) * (height + 1)) as usize],
+    };
+    let types = match matches.value_of_os("typemap") {
+        Some(path) => {
+            read_typemap(Path::new(path), width, height, &type_colors)?
+        },
+        None => vec![SectorType::Grass; (width * height) as usize],
+    };
+    let unknown = match matches.value_of_os("from") {
+        Some(path) => World::open(Path::new(path))?.unknown,
+        None => DEFAULT_UNKNOWN,
+    };
+
+    let world = build_world(
+        &map, palette.as_ref(), heights, types, unknown
+    )?;
+    create_dir_all(&options.dst_path)?;
+    let wld_path = options.dst_path.join(matches.value_of_os("wld").unwrap());
+    println!("Building {}...", get_file_name(&wld_path));
+    world.save(&wld_path)
+}
+
+fn replace(options: &Options, matches: &ArgMatches) -> max2::Result<()> {
+    let path = options.game_dir.join(matches.value_of_os("archive").unwrap());
+    let name = matches.value_of("name").unwrap();
+    let file = Path::new(matches.value_of_os("file").unwrap());
+    let threshold = match matches.value_of("threshold") {
+        Some(threshold) => threshold.parse().map_err(|_| {
+            let message = format!("\"{}\" is not a threshold", threshold);
+            max2::Error::new(ErrorKind::InvalidArgument(message))
+        })?,
+        None => DEFAULT_THRESHOLD,
+    };
+
+    let mut archive = Archive::open_mut(&path)?;
+    let asset = match archive.asset(name) {
+        Some(asset) => asset.clone(),
+        None => {
+            let message = format!("archive has no asset {}", name);
+            return Err(max2::Error::new(ErrorKind::InvalidArgument(message)));
+        },
+    };
+    println!("Replacing {} in {}...", name, get_file_name(&path));
 
+    // Images are mapped to palette of replaced one
+    let is_png = file.extension()
+        .map(|extension| extension.eq_ignore_ascii_case("png"))
+        .unwrap_or(false);
+    let replaced = match asset.kind {
+        AssetKind::ImageWithPalette if is_png => {
+            let old = archive.decode_image(&asset)?;
+            let image = IndexedImage::open_opaque(file, &old.palette)?;
+            archive.replace_image(name, &image)?
+        },
+        AssetKind::Image if is_png => {
+            let old = archive.decode_image(&asset)?;
+            let image = IndexedImage::open(file, &old.palette)?;
+            archive.replace_image(name, &image)?
+        },
+        AssetKind::SpriteContainer | AssetKind::ShadowContainer
+            if file.is_dir() => {
+            let frames = read_frames(&mut archive, &asset, file, threshold)?;
+            archive.replace_frames(name, &frames)?
+        },
+        _ => archive.replace(name, &read(file)?)?,
+    };
+
+    println!(
+        "Replaced {}: {} bytes at offset {}",
+        name, replaced.length, replaced.offset
+    );
     Ok(())
 }
 
-pub fn get_palettes(
-    res_file: &mut File, directory: &Directory
-) -> Result<Vec<[u8; 768]>, Box<dyn Error>> {
-    // Palettes start right after directory's header
-    let palettes_offset = directory.offset + directory.length;
-    res_file.seek(SeekFrom::Start(palettes_offset))?;
+/// Reads container frames saved as N.PNG, keeping hotspots of old frames
+///
+/// Sprite frames are mapped to container's palette, shadow frames are masks
+/// with alpha or brightness compared to threshold
+fn read_frames(
+    archive: &mut Archive, asset: &Asset, path: &Path, threshold: u8
+) -> max2::Result<Vec<IndexedImage>> {
+    let palette_id = archive.describe(asset)?.palette_id;
+    let palette = match palette_id {
+        Some(palette_id) => Some(find_palette(archive.palettes(), palette_id)?),
+        None => None,
+    };
+    let old_frames = archive.decode_frames(asset)?;
+
+    let mut frames: Vec<IndexedImage> = Vec::new();
+    loop {
+        let frame_path = path.join(format!("{}.PNG", frames.len()));
+        if !frame_path.is_file() {
+            break;
+        }
+        let mut frame = match &palette {
+            Some(palette) => IndexedImage::open(&frame_path, palette)?,
+            None => IndexedImage::open_mask(&frame_path, threshold)?,
+        };
+        if let Some(old) = old_frames.get(frames.len()) {
+            frame.origin_x = old.origin_x;
+            frame.origin_y = old.origin_y;
+        }
+        frame.palette_id = palette_id;
+        frames.push(frame);
+    }
 
-    // Palettes list starts from 2 bytes with palettes count
-    let mut palettes_count = [0; 2];
-    res_file.read(&mut palettes_count)?;
-    let palettes_count = buf_to_le_u32(&palettes_count)? as usize;
+    Ok(frames)
+}
 
-    // Every palette is 3 * 256 bytes
-    let mut palettes: Vec<[u8; 768]> = Vec::new();
-    while palettes.len() < palettes_count {
-        let mut palette = [0; 768];
-        res_file.read(&mut palette)?;
-        palettes.push(palette);
+fn build_res(options: &Options, matches: &ArgMatches) -> max2::Result<()> {
+    let mut builder = match matches.value_of_os("from") {
+        Some(path) => {
+            let mut archive = Archive::open(options.game_dir.join(path))?;
+            ArchiveBuilder::from_archive(&mut archive)?
+        },
+        None => ArchiveBuilder::new(),
+    };
+    if let Some(path) = matches.value_of_os("assets") {
+        add_asset_dir(&mut builder, Path::new(path))?;
     }
 
-    Ok(palettes)
+    create_dir_all(&options.dst_path)?;
+    let res_path = options.dst_path.join(matches.value_of_os("res").unwrap());
+    println!(
+        "Building {} with {} assets and {} palettes...",
+        get_file_name(&res_path),
+        builder.assets().len(),
+        builder.palettes().len()
+    );
+    builder.save(&res_path)
 }
 
-pub fn extract_assets(
-    dst_path: &PathBuf,
-    res_file: &mut File,
-    palettes: &Vec<[u8; 768]>,
-    assets: &Vec<Asset>
-) -> Result<(), Box<dyn Error>> {
-    for asset in assets {
-        // Assert that directory for type exists
-        let mut dst_type_path = dst_path.to_path_buf();
-        dst_type_path.push(asset.type_.to_string());
-        create_dir_all(&dst_type_path)?;
+/// Adds assets laid out like extracted archive directory: asset files in
+/// directories named by kind, and palettes in `palette` directory
+fn add_asset_dir(
+    builder: &mut ArchiveBuilder, path: &Path
+) -> max2::Result<()> {
+    let mut assets: Vec<(PathBuf, AssetKind)> = Vec::new();
+    for kind_path in sorted_entries(path)? {
+        if !kind_path.is_dir() {
+            continue;
+        }
 
-        // Extract asset using type based algorithm
-        match asset.type_ {
-            ASSET_IMG_WITH_PALETTE => {
-                if extract_img_with_palette(res_file, &asset, &mut dst_type_path)? {
-                    println!("Extracted {}", asset.name)
-                }
-            },
-            ASSET_IMG_CONTAINER => {
-                if extract_img_container(res_file, &palettes, &asset, &mut dst_type_path)? {
-                    println!("Extracted {}", asset.name)
-                }
-            },
-            ASSET_IMG_MONO_CONTAINER => {
-                if extract_img_mono_container(res_file, &asset, &mut dst_type_path)? {
-                    println!("Extracted {}", asset.name)
-                }
-            },
-            ASSET_IMG => {
-                if extract_img(res_file, &palettes, &asset, &mut dst_type_path)? {
-                    println!("Extracted {}", asset.name)
-                }
-            },
-            ASSET_STR | ASSET_TXT => {
-                if extract_txt(res_file, &asset, &mut dst_type_path)? {
-                    println!("Extracted {}", asset.name)
-                }
-            },
-            _ => {
-                if extract_raw(res_file, &asset, &mut dst_type_path)? {
-                    println!("Extracted {}", asset.name)
+        let kind_name = get_file_name(&kind_path);
+        if kind_name == PALETTE_DIR {
+            let mut palettes: Vec<(usize, [u8; 768])> = Vec::new();
+            for palette_path in sorted_entries(&kind_path)? {
+                // Palettes are named by their id, eg. "0.PNG"
+                let stem = palette_path.file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .and_then(|stem| stem.parse().ok());
+                if let Some(id) = stem {
+                    palettes.push((id, read_palette_image(&palette_path)?));
                 }
+            }
+            palettes.sort_by_key(|(id, _)| *id);
+            builder.set_palettes(
+                palettes.into_iter().map(|(_, palette)| palette).collect()
+            );
+            continue;
+        }
+
+        let kind = match AssetKind::parse(&kind_name) {
+            Some(kind) => kind,
+            None => {
+                let message = format!(
+                    "directory \"{}\" is not named by asset kind", kind_name
+                );
+                let kind = ErrorKind::InvalidArgument(message);
+                return Err(max2::Error::new(kind));
             },
+        };
+        for asset_path in sorted_entries(&kind_path)? {
+            if asset_path.is_file() {
+                assets.push((asset_path, kind));
+            }
         }
     }
 
+    // Assets listed in manifest keep order they had in archive, others
+    // follow them ordered by path
+    let order = read_asset_order(path)?;
+    assets.sort_by_key(|(asset_path, _)| {
+        asset_path.canonicalize().ok()
+            .and_then(|asset_path| order.get(&asset_path).cloned())
+            .unwrap_or(usize::MAX)
+    });
+    for (asset_path, kind) in assets {
+        let name = get_file_name(&asset_path);
+        builder.add(&name, kind, read(&asset_path)?);
+    }
+
     Ok(())
 }
 
-fn extract_wlds(dst_path: &PathBuf) -> Result<(), Box<dyn Error>> {
-    // Assert that directory for type exists
-    let mut dst_type_path = dst_path.to_path_buf();
-    dst_type_path.push("wld");
-    create_dir_all(&dst_type_path)?;
-
-    // Iterate WLD files in chdir
-    for wld_file in glob("*.WLD")? {
-        let wld_file = wld_file?;
-        let wld_file = wld_file.file_name();
-        if wld_file.is_some() {
-            let wld_file = wld_file.unwrap();
-            let mut wld_path = current_dir()?;
-            wld_path.push(wld_file);
-            if wld_path.is_file() {
-                println!("Extracting {}...", wld_file.to_string_lossy());
-                extract_wld(&mut wld_path, &mut dst_type_path)?;
-            }
+/// Maps files listed in manifest of extracted directory, or directory it
+/// was extracted to, to position of their asset
+fn read_asset_order(path: &Path) -> max2::Result<HashMap<PathBuf, usize>> {
+    let mut order: HashMap<PathBuf, usize> = HashMap::new();
+    let root = path.canonicalize()?;
+    let manifest_dir = root.ancestors()
+        .take(2)
+        .find(|dir| dir.join(MANIFEST_FILE).is_file());
+    let manifest_dir = match manifest_dir {
+        Some(manifest_dir) => manifest_dir,
+        None => return Ok(order),
+    };
+
+    let assets = Manifest::read_files(&manifest_dir.join(MANIFEST_FILE))?;
+    for (i, files) in assets.into_iter().enumerate() {
+        for file in files {
+            // Manifest separates path parts with "/" on every platform
+            let file_path = file.split('/')
+                .fold(manifest_dir.to_path_buf(), |path, part| path.join(part));
+            order.entry(file_path).or_insert(i);
         }
     }
+    Ok(order)
+}
 
-    Ok(())
-}
\ No newline at end of file
+/// Lists directory entries ordered by name
+fn sorted_entries(path: &Path) -> max2::Result<Vec<PathBuf>> {
+    let mut paths: Vec<PathBuf> = Vec::new();
+    for entry in read_dir(path)? {
+        paths.push(entry?.path());
+    }
+    paths.sort();
+    Ok(paths)
+}
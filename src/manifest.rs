@@ -0,0 +1,117 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+
+use super::directory::{Asset, AssetKind};
+use super::error::Result;
+
+/// Size and hotspot of single image or container frame.
+#[derive(Clone, Debug, Serialize)]
+pub struct FrameInfo {
+    pub width: u32,
+    pub height: u32,
+    /// Pixel sprite is placed by, origin or center in game data
+    pub hotspot_x: i32,
+    pub hotspot_y: i32,
+}
+
+/// Metadata read from asset headers, without decoding its pixels.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct AssetInfo {
+    /// Archive palette asset uses
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub palette_id: Option<usize>,
+    /// Bytes of unknown meaning preceding type 1 image
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unknown: Option<[u8; 4]>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub frames: Vec<FrameInfo>,
+}
+
+/// Extracted asset described in manifest.
+#[derive(Clone, Debug, Serialize)]
+pub struct ManifestEntry {
+    /// Archive file name, eg. `MAX2.RES`
+    pub archive: String,
+    pub name: String,
+    pub kind: AssetKind,
+    pub type_id: u32,
+    pub offset: u64,
+    pub length: u64,
+    #[serde(flatten)]
+    pub info: AssetInfo,
+    /// Files asset was extracted to, relative to manifest
+    pub files: Vec<String>,
+}
+
+/// List of extracted assets, written as `manifest.json`.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Manifest {
+    pub assets: Vec<ManifestEntry>,
+}
+
+/// Part of written manifest needed to find assets by their files.
+#[derive(Deserialize)]
+struct StoredManifest {
+    assets: Vec<StoredEntry>,
+}
+
+#[derive(Deserialize)]
+struct StoredEntry {
+    files: Vec<String>,
+}
+
+impl Manifest {
+    pub fn new() -> Manifest {
+        Manifest::default()
+    }
+
+    /// Adds asset with paths to files it was extracted to.
+    ///
+    /// Paths are stored relative to `root`, the manifest directory.
+    pub fn add(
+        &mut self,
+        archive: &str,
+        asset: &Asset,
+        info: AssetInfo,
+        files: &[PathBuf],
+        root: &Path
+    ) {
+        let files = files.iter().map(|path| {
+            let path = path.strip_prefix(root).unwrap_or(path);
+            // Use same separator on every platform
+            let parts: Vec<_> = path.iter()
+                .map(|part| part.to_string_lossy())
+                .collect();
+            parts.join("/")
+        }).collect();
+
+        self.assets.push(ManifestEntry {
+            archive: archive.to_string(),
+            name: asset.name.clone(),
+            kind: asset.kind,
+            type_id: asset.kind.type_id(),
+            offset: asset.offset,
+            length: asset.length,
+            info,
+            files,
+        });
+    }
+
+    /// Writes manifest as pretty printed JSON.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let file = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(file, self).map_err(io::Error::from)?;
+        Ok(())
+    }
+
+    /// Reads files of every asset listed by written manifest, in order
+    /// assets were extracted.
+    pub fn read_files(path: &Path) -> Result<Vec<Vec<String>>> {
+        let file = BufReader::new(File::open(path)?);
+        let manifest: StoredManifest = serde_json::from_reader(file)
+            .map_err(io::Error::from)?;
+        Ok(manifest.assets.into_iter().map(|entry| entry.files).collect())
+    }
+}
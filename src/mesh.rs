@@ -0,0 +1,237 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use byteorder::{LittleEndian, WriteBytesExt};
+use serde_json::json;
+
+use super::error::Result;
+use super::world::SECTOR_EDGE;
+
+/// Texture file meshes are mapped with, map render saved by `extract_wld`.
+pub const TEXTURE_FILE: &str = "full.png";
+/// Mesh file names, without extension
+pub const MESH_NAME: &str = "terrain";
+
+const MATERIAL_NAME: &str = "map";
+
+// glTF constants
+const GLTF_FLOAT: u32 = 5126;
+const GLTF_UNSIGNED_INT: u32 = 5125;
+const GLTF_ARRAY_BUFFER: u32 = 34962;
+const GLTF_ELEMENT_ARRAY_BUFFER: u32 = 34963;
+const GLTF_NEAREST: u32 = 9728;
+const GLTF_CLAMP_TO_EDGE: u32 = 33071;
+
+/// Terrain mesh with vertex in every sector corner.
+///
+/// X and Z axes run along map columns and rows, Y is vertex height.
+#[derive(Clone, Debug)]
+pub struct TerrainMesh {
+    pub positions: Vec<[f32; 3]>,
+    /// Texture coordinates with origin in top left corner of map render
+    pub uvs: Vec<[f32; 2]>,
+    /// Two triangles per sector, counter-clockwise seen from above
+    pub indices: Vec<u32>,
+}
+
+impl TerrainMesh {
+    /// Builds mesh of map `width` x `height` sectors.
+    ///
+    /// `heights` has `(width + 1) * (height + 1)` values, row after row.
+    /// Sector is 32 units wide, same as its pixels in map render.
+    pub fn new(width: u32, height: u32, heights: &[u16]) -> TerrainMesh {
+        let columns = width + 1;
+        let rows = height + 1;
+
+        let mut positions: Vec<[f32; 3]> = Vec::new();
+        let mut uvs: Vec<[f32; 2]> = Vec::new();
+        for y in 0..rows {
+            for x in 0..columns {
+                let vertex_height = heights
+                    .get((x + y * columns) as usize)
+                    .cloned()
+                    .unwrap_or(0);
+                // Sector is as many units wide as it has pixels
+                positions.push([
+                    (x * SECTOR_EDGE) as f32,
+                    vertex_height as f32,
+                    (y * SECTOR_EDGE) as f32,
+                ]);
+                // Sector corners land exactly on 32 pixels boundaries
+                uvs.push([
+                    x as f32 / width.max(1) as f32,
+                    y as f32 / height.max(1) as f32,
+                ]);
+            }
+        }
+
+        let mut indices: Vec<u32> = Vec::new();
+        for y in 0..height {
+            for x in 0..width {
+                let top_left = x + y * columns;
+                let top_right = top_left + 1;
+                let bottom_left = top_left + columns;
+                let bottom_right = bottom_left + 1;
+                indices.extend_from_slice(&[top_left, bottom_left, top_right]);
+                indices.extend_from_slice(
+                    &[top_right, bottom_left, bottom_right]
+                );
+            }
+        }
+
+        TerrainMesh { positions, uvs, indices }
+    }
+
+    /// Writes Wavefront OBJ `name`.obj with `name`.mtl material.
+    pub fn write_obj(&self, dir: &Path, name: &str) -> Result<()> {
+        let mtl_name = format!("{}.mtl", name);
+        let mut mtl = BufWriter::new(File::create(dir.join(&mtl_name))?);
+        writeln!(mtl, "newmtl {}", MATERIAL_NAME)?;
+        writeln!(mtl, "Kd 1 1 1")?;
+        writeln!(mtl, "map_Kd {}", TEXTURE_FILE)?;
+        mtl.flush()?;
+
+        let obj_path = dir.join(format!("{}.obj", name));
+        let mut obj = BufWriter::new(File::create(obj_path)?);
+        writeln!(obj, "mtllib {}", mtl_name)?;
+        writeln!(obj, "o {}", name)?;
+        for [x, y, z] in &self.positions {
+            writeln!(obj, "v {} {} {}", x, y, z)?;
+        }
+        // OBJ texture coordinates start in bottom left corner
+        for [u, v] in &self.uvs {
+            writeln!(obj, "vt {} {}", u, 1.0 - v)?;
+        }
+        writeln!(obj, "usemtl {}", MATERIAL_NAME)?;
+        for triangle in self.indices.chunks(3) {
+            // OBJ indices start from 1, vertex and its UV share index
+            let [a, b, c] = [triangle[0] + 1, triangle[1] + 1, triangle[2] + 1];
+            writeln!(obj, "f {}/{} {}/{} {}/{}", a, a, b, b, c, c)?;
+        }
+        obj.flush()?;
+
+        Ok(())
+    }
+
+    /// Writes glTF 2.0 `name`.gltf with geometry in `name`.bin.
+    pub fn write_gltf(&self, dir: &Path, name: &str) -> Result<()> {
+        // Buffer holds positions, then UVs, then indices
+        let mut buffer: Vec<u8> = Vec::new();
+        for position in &self.positions {
+            for value in position {
+                buffer.write_f32::<LittleEndian>(*value)?;
+            }
+        }
+        let uvs_offset = buffer.len();
+        for uv in &self.uvs {
+            for value in uv {
+                buffer.write_f32::<LittleEndian>(*value)?;
+            }
+        }
+        let indices_offset = buffer.len();
+        for index in &self.indices {
+            buffer.write_u32::<LittleEndian>(*index)?;
+        }
+
+        let bin_name = format!("{}.bin", name);
+        let mut bin = File::create(dir.join(&bin_name))?;
+        bin.write_all(&buffer)?;
+
+        // Position accessor has to declare its bounds
+        let mut min = [f32::MAX; 3];
+        let mut max = [f32::MIN; 3];
+        for position in &self.positions {
+            for axis in 0..3 {
+                min[axis] = min[axis].min(position[axis]);
+                max[axis] = max[axis].max(position[axis]);
+            }
+        }
+        if self.positions.is_empty() {
+            min = [0.0; 3];
+            max = [0.0; 3];
+        }
+
+        let gltf = json!({
+            "asset": {
+                "version": "2.0",
+                "generator": env!("CARGO_PKG_NAME"),
+            },
+            "scene": 0,
+            "scenes": [{ "nodes": [0] }],
+            "nodes": [{ "mesh": 0, "name": name }],
+            "meshes": [{
+                "name": name,
+                "primitives": [{
+                    "attributes": { "POSITION": 0, "TEXCOORD_0": 1 },
+                    "indices": 2,
+                    "material": 0,
+                }],
+            }],
+            "materials": [{
+                "name": MATERIAL_NAME,
+                "pbrMetallicRoughness": {
+                    "baseColorTexture": { "index": 0 },
+                    "metallicFactor": 0.0,
+                },
+            }],
+            "textures": [{ "source": 0, "sampler": 0 }],
+            "images": [{ "uri": TEXTURE_FILE }],
+            // Keep map pixels sharp
+            "samplers": [{
+                "magFilter": GLTF_NEAREST,
+                "minFilter": GLTF_NEAREST,
+                "wrapS": GLTF_CLAMP_TO_EDGE,
+                "wrapT": GLTF_CLAMP_TO_EDGE,
+            }],
+            "buffers": [{ "uri": bin_name, "byteLength": buffer.len() }],
+            "bufferViews": [
+                {
+                    "buffer": 0,
+                    "byteOffset": 0,
+                    "byteLength": uvs_offset,
+                    "target": GLTF_ARRAY_BUFFER,
+                },
+                {
+                    "buffer": 0,
+                    "byteOffset": uvs_offset,
+                    "byteLength": indices_offset - uvs_offset,
+                    "target": GLTF_ARRAY_BUFFER,
+                },
+                {
+                    "buffer": 0,
+                    "byteOffset": indices_offset,
+                    "byteLength": buffer.len() - indices_offset,
+                    "target": GLTF_ELEMENT_ARRAY_BUFFER,
+                },
+            ],
+            "accessors": [
+                {
+                    "bufferView": 0,
+                    "componentType": GLTF_FLOAT,
+                    "count": self.positions.len(),
+                    "type": "VEC3",
+                    "min": min,
+                    "max": max,
+                },
+                {
+                    "bufferView": 1,
+                    "componentType": GLTF_FLOAT,
+                    "count": self.uvs.len(),
+                    "type": "VEC2",
+                },
+                {
+                    "bufferView": 2,
+                    "componentType": GLTF_UNSIGNED_INT,
+                    "count": self.indices.len(),
+                    "type": "SCALAR",
+                },
+            ],
+        });
+
+        let gltf_path = dir.join(format!("{}.gltf", name));
+        let file = BufWriter::new(File::create(gltf_path)?);
+        serde_json::to_writer_pretty(file, &gltf).map_err(io::Error::from)?;
+
+        Ok(())
+    }
+}
@@ -1,10 +1,104 @@
-use std::error::Error;
-use std::path::PathBuf;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
 use image::{ImageBuffer, Rgb, RgbImage};
 
+use super::directory::Directory;
+use super::error::{Error, ErrorKind, Result};
+use super::utils::{buf_to_le_u32, read_buf};
+
+// Every palette is 3 * 256 bytes
+const PALETTE_LEN: u64 = 3 * 256;
+
+pub fn get_palettes<R: Read + Seek>(
+    res_file: &mut R, directory: &Directory
+) -> Result<Vec<[u8; 768]>> {
+    // Palettes start right after directory's header
+    let palettes_offset = directory.offset + directory.length;
+    let file_len = res_file.seek(SeekFrom::End(0))?;
+    // Not every archive has palettes (eg. MAX2.CAF doesn't)
+    if file_len < palettes_offset + 2 {
+        return Ok(Vec::new());
+    }
+
+    res_file.seek(SeekFrom::Start(palettes_offset))?;
+
+    // Palettes list starts from 2 bytes with palettes count
+    let mut palettes_count = [0; 2];
+    read_buf(res_file, &mut palettes_count)?;
+    let palettes_count = buf_to_le_u32(&palettes_count)? as usize;
+
+    // If count doesn't fit in file, data after directory is not palettes
+    let palettes_len = palettes_count as u64 * PALETTE_LEN;
+    if file_len < palettes_offset + 2 + palettes_len {
+        return Ok(Vec::new());
+    }
+
+    let mut palettes: Vec<[u8; 768]> = Vec::new();
+    while palettes.len() < palettes_count {
+        let mut palette = [0; 768];
+        read_buf(res_file, &mut palette)?;
+        palettes.push(palette);
+    }
+
+    Ok(palettes)
+}
+
+/// Writes palettes list in the layout `get_palettes` reads it.
+pub fn write_palettes<W: Write>(
+    res_file: &mut W, palettes: &[[u8; 768]]
+) -> Result<()> {
+    if palettes.len() > u16::MAX as usize {
+        let message = format!("{} palettes don't fit archive", palettes.len());
+        return Err(Error::new(ErrorKind::InvalidArgument(message)));
+    }
+
+    res_file.write_all(&(palettes.len() as u16).to_le_bytes())?;
+    for palette in palettes {
+        res_file.write_all(palette)?;
+    }
+
+    Ok(())
+}
+
+/// Returns copy of palette with given id.
+pub fn find_palette(
+    palettes: &[[u8; 768]], palette_id: usize
+) -> Result<[u8; 768]> {
+    match palettes.get(palette_id) {
+        Some(palette) => Ok(*palette),
+        None => {
+            let palettes_count = palettes.len();
+            let kind = ErrorKind::PaletteOutOfRange {
+                palette_id, palettes_count
+            };
+            Err(Error::new(kind))
+        },
+    }
+}
+
+/// Reads palette from 16x16 image saved by `render_palette`.
+pub fn read_palette_image(path: &Path) -> Result<[u8; 768]> {
+    let img = image::open(path)?.to_rgb();
+    if img.dimensions() != (16, 16) {
+        let message = format!(
+            "palette image {} is not 16x16 pixels", path.display()
+        );
+        return Err(Error::new(ErrorKind::InvalidArgument(message)));
+    }
+
+    let mut palette = [0u8; 768];
+    for (x, y, pixel) in img.enumerate_pixels() {
+        let start = (x + (y * 16)) as usize * 3;
+        let color = [pixel[0], pixel[1], pixel[2]];
+        palette[start..start + 3].copy_from_slice(&color);
+    }
+
+    Ok(palette)
+}
+
 pub fn render_palette(
-    dst: &PathBuf, palette: &[u8; 768]
-) -> Result<bool, Box<dyn Error>> {
+    dst: &Path, palette: &[u8; 768]
+) -> Result<bool> {
     if dst.is_file() {
         return Ok(false) // Skip file
     }
@@ -25,4 +119,4 @@ pub fn render_palette(
     img.save(dst)?;
 
     Ok(true)
-}
\ No newline at end of file
+}
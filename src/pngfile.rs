@@ -0,0 +1,168 @@
+use std::io::Write;
+use byteorder::{BigEndian, WriteBytesExt};
+use crc32fast::Hasher;
+use deflate::deflate_bytes_zlib;
+
+use super::error::Result;
+
+const SIGNATURE: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+// Palette based image, 1 byte per pixel
+const INDEXED_BIT_DEPTH: u8 = 8;
+const COLOR_TYPE_INDEXED: u8 = 3;
+// Grayscale image, 2 bytes per pixel
+const GRAY_BIT_DEPTH: u8 = 16;
+const COLOR_TYPE_GRAY: u8 = 0;
+// Frame area is cleared to transparent before next frame is drawn
+const DISPOSE_OP_BACKGROUND: u8 = 1;
+// Frame replaces canvas pixels instead of being blended with them
+const BLEND_OP_SOURCE: u8 = 0;
+
+/// Writes 8-bit palette indexed PNG.
+///
+/// Pixels with `transparent` index are marked as fully transparent.
+pub fn write_png<W: Write>(
+    writer: &mut W,
+    width: u32,
+    height: u32,
+    palette: &[u8; 768],
+    transparent: Option<u8>,
+    pixels: &[u8]
+) -> Result<()> {
+    write_header(writer, width, height, palette, transparent)?;
+    write_chunk(writer, b"IDAT", &compress_pixels(pixels, width))?;
+    write_chunk(writer, b"IEND", &[])?;
+    Ok(())
+}
+
+/// Writes 16-bit grayscale PNG.
+pub fn write_png_gray16<W: Write>(
+    writer: &mut W, width: u32, height: u32, values: &[u16]
+) -> Result<()> {
+    writer.write_all(&SIGNATURE)?;
+    write_ihdr(writer, width, height, GRAY_BIT_DEPTH, COLOR_TYPE_GRAY)?;
+
+    // PNG samples are big endian
+    let mut pixels: Vec<u8> = Vec::with_capacity(values.len() * 2);
+    for value in values {
+        pixels.write_u16::<BigEndian>(*value)?;
+    }
+    write_chunk(writer, b"IDAT", &compress_pixels(&pixels, width * 2))?;
+    write_chunk(writer, b"IEND", &[])?;
+    Ok(())
+}
+
+/// Writes animated PNG of palette indexed frames, all of canvas size.
+///
+/// `delay` is time every frame is shown for, in milliseconds.
+pub fn write_apng<W: Write>(
+    writer: &mut W,
+    width: u32,
+    height: u32,
+    palette: &[u8; 768],
+    transparent: Option<u8>,
+    frames: &[Vec<u8>],
+    delay: u16
+) -> Result<()> {
+    write_header(writer, width, height, palette, transparent)?;
+
+    // Animation control: number of frames, then plays (0 is infinite)
+    let mut actl: Vec<u8> = Vec::new();
+    actl.write_u32::<BigEndian>(frames.len() as u32)?;
+    actl.write_u32::<BigEndian>(0)?;
+    write_chunk(writer, b"acTL", &actl)?;
+
+    // Frame control and data chunks share sequence numbers
+    let mut sequence: u32 = 0;
+    for (i, pixels) in frames.iter().enumerate() {
+        let mut fctl: Vec<u8> = Vec::new();
+        fctl.write_u32::<BigEndian>(sequence)?;
+        fctl.write_u32::<BigEndian>(width)?;
+        fctl.write_u32::<BigEndian>(height)?;
+        // Frame is placed at canvas top left corner
+        fctl.write_u32::<BigEndian>(0)?;
+        fctl.write_u32::<BigEndian>(0)?;
+        fctl.write_u16::<BigEndian>(delay)?;
+        fctl.write_u16::<BigEndian>(1000)?;
+        fctl.push(DISPOSE_OP_BACKGROUND);
+        fctl.push(BLEND_OP_SOURCE);
+        write_chunk(writer, b"fcTL", &fctl)?;
+        sequence += 1;
+
+        // First frame doubles as default image
+        let data = compress_pixels(pixels, width);
+        if i == 0 {
+            write_chunk(writer, b"IDAT", &data)?;
+        } else {
+            let mut fdat: Vec<u8> = Vec::new();
+            fdat.write_u32::<BigEndian>(sequence)?;
+            fdat.extend_from_slice(&data);
+            write_chunk(writer, b"fdAT", &fdat)?;
+            sequence += 1;
+        }
+    }
+
+    write_chunk(writer, b"IEND", &[])?;
+    Ok(())
+}
+
+/// Writes signature, image header, palette and its transparent index.
+fn write_header<W: Write>(
+    writer: &mut W,
+    width: u32,
+    height: u32,
+    palette: &[u8; 768],
+    transparent: Option<u8>
+) -> Result<()> {
+    writer.write_all(&SIGNATURE)?;
+    write_ihdr(writer, width, height, INDEXED_BIT_DEPTH, COLOR_TYPE_INDEXED)?;
+    write_chunk(writer, b"PLTE", palette)?;
+
+    // Alpha of every palette entry up to transparent one
+    if let Some(transparent) = transparent {
+        let mut trns = vec![255u8; transparent as usize + 1];
+        trns[transparent as usize] = 0;
+        write_chunk(writer, b"tRNS", &trns)?;
+    }
+
+    Ok(())
+}
+
+fn write_ihdr<W: Write>(
+    writer: &mut W, width: u32, height: u32, bit_depth: u8, color_type: u8
+) -> Result<()> {
+    // Compression, filter and interlace methods are always 0
+    let mut ihdr: Vec<u8> = Vec::new();
+    ihdr.write_u32::<BigEndian>(width)?;
+    ihdr.write_u32::<BigEndian>(height)?;
+    ihdr.extend_from_slice(&[bit_depth, color_type, 0, 0, 0]);
+    write_chunk(writer, b"IHDR", &ihdr)
+}
+
+/// Compresses pixels, prefixing every row with "none" filter type.
+///
+/// `row_len` is length of row in bytes.
+fn compress_pixels(pixels: &[u8], row_len: u32) -> Vec<u8> {
+    let mut data: Vec<u8> = Vec::new();
+    if row_len > 0 {
+        for row in pixels.chunks(row_len as usize) {
+            data.push(0);
+            data.extend_from_slice(row);
+        }
+    }
+    deflate_bytes_zlib(&data)
+}
+
+fn write_chunk<W: Write>(
+    writer: &mut W, kind: &[u8; 4], data: &[u8]
+) -> Result<()> {
+    writer.write_u32::<BigEndian>(data.len() as u32)?;
+    writer.write_all(kind)?;
+    writer.write_all(data)?;
+
+    // Checksum covers chunk type and data
+    let mut hasher = Hasher::new();
+    hasher.update(kind);
+    hasher.update(data);
+    writer.write_u32::<BigEndian>(hasher.finalize())?;
+    Ok(())
+}
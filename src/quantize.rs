@@ -0,0 +1,139 @@
+use std::collections::HashMap;
+
+// Palette has 256 colors
+const PALETTE_SIZE: usize = 256;
+
+/// Builds palette for colors and maps every color to its index.
+///
+/// Images with up to 256 colors keep them exactly, in order of first use.
+/// Other images are reduced with median cut.
+pub fn quantize(colors: &[[u8; 3]]) -> ([u8; 768], Vec<u8>) {
+    // Unique colors with number of pixels using them
+    let mut counts: HashMap<[u8; 3], usize> = HashMap::new();
+    let mut unique: Vec<[u8; 3]> = Vec::new();
+    for color in colors {
+        let count = counts.entry(*color).or_insert(0);
+        if *count == 0 {
+            unique.push(*color);
+        }
+        *count += 1;
+    }
+
+    let palette_colors = if unique.len() <= PALETTE_SIZE {
+        unique
+    } else {
+        let weighted = unique.iter().map(|color| (*color, counts[color]));
+        median_cut(weighted.collect())
+    };
+
+    let mut palette = [0u8; 768];
+    for (entry, color) in palette.chunks_mut(3).zip(palette_colors.iter()) {
+        entry.copy_from_slice(color);
+    }
+
+    (palette, remap(colors, &palette))
+}
+
+/// Maps every color to index of nearest palette color.
+pub fn remap(colors: &[[u8; 3]], palette: &[u8; 768]) -> Vec<u8> {
+    let mut cache: HashMap<[u8; 3], u8> = HashMap::new();
+    colors.iter()
+        .map(|color| {
+            *cache.entry(*color)
+                .or_insert_with(|| nearest_index(palette, *color))
+        })
+        .collect()
+}
+
+/// Returns index of palette color closest to given one.
+///
+/// First of equally close colors is used, so exact matches keep index.
+pub fn nearest_index(palette: &[u8; 768], color: [u8; 3]) -> u8 {
+    let mut nearest = 0;
+    let mut nearest_distance = u32::MAX;
+    for (index, entry) in palette.chunks(3).enumerate() {
+        let distance: u32 = entry.iter().zip(color.iter())
+            .map(|(a, b)| {
+                let delta = *a as i32 - *b as i32;
+                (delta * delta) as u32
+            })
+            .sum();
+        if distance < nearest_distance {
+            nearest = index;
+            nearest_distance = distance;
+            if distance == 0 {
+                break;
+            }
+        }
+    }
+    nearest as u8
+}
+
+/// Splits colors into up to 256 boxes, returning average color of each.
+fn median_cut(colors: Vec<([u8; 3], usize)>) -> Vec<[u8; 3]> {
+    let mut boxes: Vec<Vec<([u8; 3], usize)>> = vec![colors];
+    while boxes.len() < PALETTE_SIZE {
+        // Split box spanning widest range of single channel
+        let widest = boxes.iter().enumerate()
+            .filter(|(_, colors)| colors.len() > 1)
+            .map(|(i, colors)| (i, widest_channel(colors)))
+            .max_by_key(|(_, (_, range))| *range);
+        let (i, channel) = match widest {
+            Some((i, (channel, _))) => (i, channel),
+            None => break,
+        };
+
+        let mut colors = boxes.swap_remove(i);
+        colors.sort_by_key(|(color, _)| color[channel]);
+
+        // Split at median pixel, keeping at least one color in each half
+        let total: usize = colors.iter().map(|(_, count)| count).sum();
+        let mut pixels = 0;
+        let mut split = 1;
+        for (j, (_, count)) in colors.iter().enumerate() {
+            pixels += count;
+            if pixels * 2 >= total {
+                split = j + 1;
+                break;
+            }
+        }
+        let split = split.min(colors.len() - 1);
+
+        let second = colors.split_off(split);
+        boxes.push(colors);
+        boxes.push(second);
+    }
+
+    boxes.iter().map(|colors| average(colors)).collect()
+}
+
+/// Returns channel with widest range of values, and that range.
+fn widest_channel(colors: &[([u8; 3], usize)]) -> (usize, u8) {
+    (0..3)
+        .map(|channel| {
+            let values = colors.iter().map(|(color, _)| color[channel]);
+            let min = values.clone().min().unwrap_or(0);
+            let max = values.max().unwrap_or(0);
+            (channel, max - min)
+        })
+        .max_by_key(|(_, range)| *range)
+        .unwrap_or((0, 0))
+}
+
+/// Returns average of colors weighted by their pixel counts.
+fn average(colors: &[([u8; 3], usize)]) -> [u8; 3] {
+    let mut sums = [0u64; 3];
+    let mut total = 0u64;
+    for (color, count) in colors {
+        for (sum, value) in sums.iter_mut().zip(color.iter()) {
+            *sum += *value as u64 * *count as u64;
+        }
+        total += *count as u64;
+    }
+
+    let mut color = [0u8; 3];
+    for (value, sum) in color.iter_mut().zip(sums.iter()) {
+        *value = (*sum / total.max(1)) as u8;
+    }
+    color
+}
@@ -1,13 +1,25 @@
-use std::error::Error;
 use std::fs::File;
 use std::io::{Read, Seek, SeekFrom, Write};
 use std::path::PathBuf;
 
 use super::directory::Asset;
+use super::error::Result;
+use super::utils::read_buf;
 
-pub fn extract_raw(
-    res_file: &mut File, asset: &Asset, path: &mut PathBuf
-) -> Result<bool, Box<dyn Error>> {
+pub fn read_raw<R: Read + Seek>(
+    res_file: &mut R, asset: &Asset
+) -> Result<Vec<u8>> {
+    // Jump to asset start, then read its length
+    res_file.seek(SeekFrom::Start(asset.offset))?;
+    let mut data = vec![0u8; asset.length as usize];
+    read_buf(res_file, &mut data)?;
+
+    Ok(data)
+}
+
+pub fn extract_raw<R: Read + Seek>(
+    res_file: &mut R, asset: &Asset, path: &mut PathBuf
+) -> Result<bool> {
     // Add filename to path
     path.push(&asset.name);
 
@@ -16,14 +28,11 @@ pub fn extract_raw(
         return Ok(false);
     }
 
-    // Jump to asset start, then read its length
-    res_file.seek(SeekFrom::Start(asset.offset))?;
-    let mut data = vec![0u8; asset.length as usize];
-    res_file.read(&mut data)?;
+    let data = read_raw(res_file, asset)?;
 
     // Write file data
     let mut output = File::create(path)?;
-    output.write_all(&data.as_mut_slice())?;
+    output.write_all(&data)?;
 
     Ok(true)
-}
\ No newline at end of file
+}
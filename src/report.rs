@@ -0,0 +1,145 @@
+use std::fmt;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+use super::directory::AssetKind;
+use super::error::{Error, Result};
+
+/// Asset or file that failed to extract.
+#[derive(Clone, Debug)]
+pub struct Failure {
+    /// Archive or file being extracted, eg. `MAX2.RES`
+    pub source: String,
+    pub asset: Option<String>,
+    pub asset_kind: Option<AssetKind>,
+    pub message: String,
+}
+
+/// Problem found in file that was extracted anyway.
+#[derive(Clone, Debug)]
+pub struct Warning {
+    pub source: String,
+    pub message: String,
+}
+
+/// Failures and warnings collected during extraction run.
+#[derive(Clone, Debug, Default)]
+pub struct Report {
+    pub failures: Vec<Failure>,
+    pub warnings: Vec<Warning>,
+}
+
+impl Report {
+    pub fn new() -> Report {
+        Report::default()
+    }
+
+    pub fn has_failures(&self) -> bool {
+        !self.failures.is_empty()
+    }
+
+    pub fn has_warnings(&self) -> bool {
+        !self.warnings.is_empty()
+    }
+
+    /// Records error, taking asset name and type from it.
+    pub fn add_error(&mut self, source: &str, error: &Error) {
+        let mut message = error.kind.to_string();
+        if let Some(offset) = error.offset {
+            message.push_str(&format!(" at offset {}", offset));
+        }
+
+        self.failures.push(Failure {
+            source: source.to_string(),
+            asset: error.asset.as_ref().map(|asset| asset.name.clone()),
+            asset_kind: error.asset.as_ref().map(|asset| asset.kind),
+            message,
+        });
+    }
+
+    /// Records failure that didn't come from decoder, eg. filesystem one.
+    pub fn add_failure(
+        &mut self, source: &str, asset: Option<&str>, message: &str
+    ) {
+        self.failures.push(Failure {
+            source: source.to_string(),
+            asset: asset.map(String::from),
+            asset_kind: None,
+            message: message.to_string(),
+        });
+    }
+
+    pub fn add_warning(&mut self, source: &str, message: &str) {
+        self.warnings.push(Warning {
+            source: source.to_string(),
+            message: message.to_string(),
+        });
+    }
+
+    /// Writes report as plain text table.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let mut file = File::create(path)?;
+        write!(file, "{}", self)?;
+        Ok(())
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.failures.is_empty() {
+            writeln!(f, "No failures")?;
+        } else {
+            self.fmt_failures(f)?;
+        }
+
+        if self.has_warnings() {
+            writeln!(f)?;
+            for warning in &self.warnings {
+                writeln!(f, "{}: {}", warning.source, warning.message)?;
+            }
+            writeln!(f, "{} warnings", self.warnings.len())?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Report {
+    fn fmt_failures(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Table rows, starting with header
+        let mut rows = vec![[
+            String::from("Source"),
+            String::from("Asset"),
+            String::from("Type"),
+            String::from("Error"),
+        ]];
+        for failure in &self.failures {
+            rows.push([
+                failure.source.clone(),
+                failure.asset.clone().unwrap_or_default(),
+                failure.asset_kind.map(|k| k.name()).unwrap_or_default(),
+                failure.message.clone(),
+            ]);
+        }
+
+        // Pad every column but last to its widest cell
+        let mut widths = [0; 3];
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row.iter()) {
+                *width = (*width).max(cell.len());
+            }
+        }
+
+        for row in rows {
+            writeln!(
+                f,
+                "{:w0$}  {:w1$}  {:w2$}  {}",
+                row[0], row[1], row[2], row[3],
+                w0 = widths[0], w1 = widths[1], w2 = widths[2]
+            )?;
+        }
+
+        writeln!(f, "{} failures", self.failures.len())
+    }
+}
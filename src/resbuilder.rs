@@ -0,0 +1,123 @@
+use std::fs::File;
+use std::io::{BufWriter, Read, Seek, Write};
+use std::path::Path;
+
+use super::archive::Archive;
+use super::directory::{
+    directory_len, write_directory, Asset, AssetKind, DATA_OFFSET
+};
+use super::error::{Error, ErrorKind, Result};
+use super::palette::write_palettes;
+use super::resfile::write_res_file_header;
+
+/// Asset of archive being built, with data as it is stored in archive.
+#[derive(Clone, Debug)]
+pub struct AssetData {
+    pub name: String,
+    pub kind: AssetKind,
+    pub data: Vec<u8>,
+}
+
+/// Builds `RES0` package from assets and palettes.
+///
+/// Assets data is stored in the order assets were added, right after the
+/// header. Directory follows it, then palettes, if there are any.
+#[derive(Clone, Debug, Default)]
+pub struct ArchiveBuilder {
+    assets: Vec<AssetData>,
+    palettes: Vec<[u8; 768]>,
+}
+
+impl ArchiveBuilder {
+    /// Starts empty archive, without assets and palettes.
+    pub fn new() -> ArchiveBuilder {
+        ArchiveBuilder::default()
+    }
+
+    /// Starts from copy of every asset and palette of existing archive.
+    pub fn from_archive<R: Read + Seek>(
+        archive: &mut Archive<R>
+    ) -> Result<ArchiveBuilder> {
+        let mut builder = ArchiveBuilder::new();
+        for asset in archive.assets().to_vec() {
+            let data = archive.read_stored(&asset)?;
+            let Asset { name, kind, .. } = asset;
+            builder.assets.push(AssetData { name, kind, data });
+        }
+        builder.palettes = archive.palettes().to_vec();
+
+        Ok(builder)
+    }
+
+    /// Adds asset at the end, or replaces asset of the same name in place.
+    pub fn add(&mut self, name: &str, kind: AssetKind, data: Vec<u8>) {
+        let asset = AssetData { name: String::from(name), kind, data };
+        match self.assets.iter_mut().find(|old| old.name == name) {
+            Some(old) => *old = asset,
+            None => self.assets.push(asset),
+        }
+    }
+
+    /// Lists assets in order they will be stored.
+    pub fn assets(&self) -> &[AssetData] {
+        &self.assets
+    }
+
+    pub fn add_palette(&mut self, palette: [u8; 768]) {
+        self.palettes.push(palette);
+    }
+
+    /// Replaces all palettes. Archive without palettes has no palettes list.
+    pub fn set_palettes(&mut self, palettes: Vec<[u8; 768]>) {
+        self.palettes = palettes;
+    }
+
+    pub fn palettes(&self) -> &[[u8; 768]] {
+        &self.palettes
+    }
+
+    /// Writes archive to file, replacing it if it exists.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        self.write(&mut file)?;
+        file.flush()?;
+        Ok(())
+    }
+
+    /// Writes archive in `RES0` format.
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        // Lay assets out one after another to know directory offset
+        let mut offset = DATA_OFFSET;
+        let mut directory: Vec<Asset> = Vec::with_capacity(self.assets.len());
+        for asset in &self.assets {
+            let length = asset.data.len() as u64;
+            directory.push(Asset {
+                offset,
+                length,
+                kind: asset.kind,
+                name: asset.name.clone(),
+            });
+            offset += length;
+        }
+
+        let directory_length = directory_len(&directory);
+        if offset + directory_length > u32::MAX as u64 {
+            let message = String::from("assets don't fit 4 GB archive");
+            return Err(Error::new(ErrorKind::InvalidArgument(message)));
+        }
+
+        write_res_file_header(
+            writer, offset as u32, directory_length as u32
+        )?;
+        for asset in &self.assets {
+            writer.write_all(&asset.data)?;
+        }
+        write_directory(writer, &directory)?;
+        // Not every archive has palettes (eg. MAX2.CAF doesn't)
+        if !self.palettes.is_empty() {
+            write_palettes(writer, &self.palettes)?;
+        }
+
+        Ok(())
+    }
+}
@@ -1,38 +1,54 @@
-use std::env::current_dir;
-use std::error;
-use std::fs::File;
-use std::io::{Error, ErrorKind, Read};
-use std::path::PathBuf;
-use std::str;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use super::directory::write_directory_header;
+use super::error::{Error, ErrorKind, Result};
+use super::utils::read_buf;
 
 const RES_FILE_HEADER: &str = "RES0";
 const INVALID_HEADER_ERROR: &str = "Opened file is not a valid RES package";
+// Header is followed by 2 unused bytes, then by directory header
+const RES_FILE_UNUSED: [u8; 2] = [0, 0];
 
-pub fn open_res_file(file_name: &str) -> Result<File, Box<dyn error::Error>> {
-    let path = get_file_path(file_name)?;
+pub fn open_res_file<P: AsRef<Path>>(
+    path: P
+) -> Result<File> {
     let mut file = File::open(path)?;
     check_res_file_header(&mut file)?;
     Ok(file)
 }
 
-fn get_file_path(file_name: &str) -> Result<PathBuf, Error> {
-    let mut path = current_dir()?;
-    path.push(file_name);
-    Ok(path)
+/// Opens RES package for reading and writing.
+pub fn open_res_file_mut<P: AsRef<Path>>(
+    path: P
+) -> Result<File> {
+    let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+    check_res_file_header(&mut file)?;
+    Ok(file)
 }
 
-fn check_res_file_header(
-    res_file: &mut File
-) -> Result<(), Box<dyn error::Error>> {
+pub fn check_res_file_header<R: Read + Seek>(
+    res_file: &mut R
+) -> Result<()> {
     // First 4 bytes should be "RES0" string
+    res_file.seek(SeekFrom::Start(0))?;
     let mut buffer = [0; 4];
-    res_file.read(&mut buffer)?;
+    read_buf(res_file, &mut buffer)?;
 
-    let header = str::from_utf8(&buffer)?;
-    if header != RES_FILE_HEADER {
-        let err = Error::new(ErrorKind::InvalidData, INVALID_HEADER_ERROR);
-        return Err(Box::new(err));
+    if buffer != RES_FILE_HEADER.as_bytes() {
+        let kind = ErrorKind::InvalidHeader(INVALID_HEADER_ERROR.to_string());
+        return Err(Error::new(kind).at(0));
     }
 
     Ok(())
 }
+
+/// Writes file header and directory header pointing at directory.
+pub fn write_res_file_header<W: Write>(
+    res_file: &mut W, directory_offset: u32, directory_length: u32
+) -> Result<()> {
+    res_file.write_all(RES_FILE_HEADER.as_bytes())?;
+    res_file.write_all(&RES_FILE_UNUSED)?;
+    write_directory_header(res_file, directory_offset, directory_length)
+}
@@ -1,13 +1,14 @@
-use std::error::Error;
 use std::fs::File;
-use std::io::{Read, Seek, SeekFrom, Write};
+use std::io::{Read, Seek, Write};
 use std::path::PathBuf;
 
 use super::directory::Asset;
+use super::error::Result;
+use super::raw::read_raw;
 
-pub fn extract_txt(
-    res_file: &mut File, asset: &Asset, path: &mut PathBuf
-) -> Result<bool, Box<dyn Error>> {
+pub fn extract_txt<R: Read + Seek>(
+    res_file: &mut R, asset: &Asset, path: &mut PathBuf
+) -> Result<bool> {
     // Add filename to path
     path.push(&asset.name);
     path.set_extension("TXT");
@@ -17,14 +18,12 @@ pub fn extract_txt(
         return Ok(false);
     }
 
-    // Jump to asset start, then read its length
-    res_file.seek(SeekFrom::Start(asset.offset))?;
-    let mut data = vec![0u8; asset.length as usize];
-    res_file.read(&mut data)?;
+    // Text assets are stored as is
+    let data = read_raw(res_file, asset)?;
 
     // Write file data
     let mut output = File::create(path)?;
-    output.write_all(&data.as_mut_slice())?;
+    output.write_all(&data)?;
 
     Ok(true)
-}
\ No newline at end of file
+}
@@ -0,0 +1,394 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use image::{ImageBuffer, Rgb, RgbImage};
+use serde_json::{json, Value};
+
+use super::error::Result;
+use super::indexed::{IndexedImage, Transparency};
+use super::typemap::TypeColors;
+use super::world::{SectorType, World, SECTOR_EDGE, SECTOR_TYPES};
+
+/// Tiled map file name, without extension
+pub const TILED_MAP_NAME: &str = "map";
+/// Image of deduplicated map tiles
+pub const TILESET_FILE: &str = "tileset.png";
+/// Image with solid tile of every sector type
+pub const TYPES_TILESET_FILE: &str = "types.png";
+
+const TILESET_COLUMNS: u32 = 16;
+const TILED_VERSION: &str = "1.2";
+
+/// Format Tiled map is saved in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TiledFormat {
+    Tmx,
+    Json,
+}
+
+impl TiledFormat {
+    /// Parses "tmx" or "json".
+    pub fn parse(value: &str) -> Option<TiledFormat> {
+        match value.to_lowercase().as_str() {
+            "tmx" => Some(TiledFormat::Tmx),
+            "json" => Some(TiledFormat::Json),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            TiledFormat::Tmx => "tmx",
+            TiledFormat::Json => "json",
+        }
+    }
+}
+
+/// WLD map split into unique 32x32 tiles, with typemap and heightmap.
+#[derive(Clone, Debug)]
+pub struct TiledMap {
+    pub width: u32,
+    pub height: u32,
+    /// Palette indices of every unique tile
+    pub tiles: Vec<Vec<u8>>,
+    /// Unique tile of every sector, row after row, `None` if it's missing
+    pub sectors: Vec<Option<u32>>,
+    /// Type of every sector, row after row
+    pub types: Vec<SectorType>,
+    /// Height of every sector corner, row after row
+    pub heights: Vec<u16>,
+}
+
+// Tileset as referenced by map
+struct Tileset {
+    name: &'static str,
+    image: &'static str,
+    first_gid: u32,
+    tile_count: u32,
+    /// Names of tiles, set as their "name" property
+    names: Vec<(u32, String)>,
+}
+
+impl Tileset {
+    fn columns(&self) -> u32 {
+        TILESET_COLUMNS.min(self.tile_count).max(1)
+    }
+
+    fn image_width(&self) -> u32 {
+        self.columns() * SECTOR_EDGE
+    }
+
+    fn image_height(&self) -> u32 {
+        let columns = self.columns();
+        let rows = (self.tile_count + columns - 1) / columns;
+        rows.max(1) * SECTOR_EDGE
+    }
+}
+
+impl TiledMap {
+    /// Deduplicates tiles used by map sectors.
+    pub fn new(world: &World) -> TiledMap {
+        let mut unique: HashMap<&[u8], u32> = HashMap::new();
+        let mut unique_tiles: Vec<Vec<u8>> = Vec::new();
+        let mut sectors: Vec<Option<u32>> = Vec::new();
+        for tile_id in &world.tile_ids {
+            let tile = match world.tile(*tile_id) {
+                Some(tile) => tile,
+                None => {
+                    sectors.push(None);
+                    continue;
+                },
+            };
+            let index = *unique.entry(tile).or_insert_with(|| {
+                unique_tiles.push(tile.to_vec());
+                unique_tiles.len() as u32 - 1
+            });
+            sectors.push(Some(index));
+        }
+
+        TiledMap {
+            width: world.width,
+            height: world.height,
+            tiles: unique_tiles,
+            sectors,
+            types: world.types.clone(),
+            heights: world.heights.clone(),
+        }
+    }
+
+    /// Places unique tiles left to right, 16 in a row.
+    pub fn tileset_image(&self, palette: &[u8; 768]) -> IndexedImage {
+        let tileset = self.tileset();
+        let width = tileset.image_width();
+        let height = tileset.image_height();
+        let columns = tileset.columns();
+
+        let mut pixels = vec![0u8; (width * height) as usize];
+        for (i, tile) in self.tiles.iter().enumerate() {
+            let left = (i as u32 % columns) * SECTOR_EDGE;
+            let top = (i as u32 / columns) * SECTOR_EDGE;
+            for (y, row) in tile.chunks(SECTOR_EDGE as usize).enumerate() {
+                let start = (left + (top + y as u32) * width) as usize;
+                pixels[start..start + row.len()].copy_from_slice(row);
+            }
+        }
+
+        IndexedImage {
+            width,
+            height,
+            origin_x: 0,
+            origin_y: 0,
+            pixels,
+            palette_id: None,
+            palette: *palette,
+            transparency: Transparency::None,
+        }
+    }
+
+    /// Draws solid tile in color of every known sector type.
+    pub fn types_image(&self, colors: &TypeColors) -> RgbImage {
+        let tileset = self.types_tileset();
+        let columns = tileset.columns();
+        let mut img: RgbImage = ImageBuffer::new(
+            tileset.image_width(), tileset.image_height()
+        );
+        for (x, y, pixel) in img.enumerate_pixels_mut() {
+            let type_ = (x / SECTOR_EDGE) + (y / SECTOR_EDGE) * columns;
+            let color = SECTOR_TYPES.get(type_ as usize)
+                .and_then(|type_| colors.get(*type_))
+                .unwrap_or([0, 0, 0]);
+            *pixel = Rgb(color);
+        }
+        img
+    }
+
+    /// Writes map in Tiled JSON format.
+    pub fn write_json(&self, path: &Path) -> Result<()> {
+        let tilesets: Vec<Value> = self.tilesets().iter()
+            .map(|tileset| {
+                let tiles: Vec<Value> = tileset.names.iter()
+                    .map(|(id, name)| json!({
+                        "id": id,
+                        "properties": [{
+                            "name": "name",
+                            "type": "string",
+                            "value": name,
+                        }],
+                    }))
+                    .collect();
+                json!({
+                    "firstgid": tileset.first_gid,
+                    "name": tileset.name,
+                    "image": tileset.image,
+                    "imagewidth": tileset.image_width(),
+                    "imageheight": tileset.image_height(),
+                    "tilewidth": SECTOR_EDGE,
+                    "tileheight": SECTOR_EDGE,
+                    "tilecount": tileset.tile_count,
+                    "columns": tileset.columns(),
+                    "margin": 0,
+                    "spacing": 0,
+                    "tiles": tiles,
+                })
+            })
+            .collect();
+
+        let layers: Vec<Value> = self.layers().into_iter().enumerate()
+            .map(|(i, (name, data))| json!({
+                "id": i + 1,
+                "name": name,
+                "type": "tilelayer",
+                "x": 0,
+                "y": 0,
+                "width": self.width,
+                "height": self.height,
+                "opacity": 1,
+                "visible": true,
+                "data": data,
+            }))
+            .collect();
+
+        let properties: Vec<Value> = self.properties().into_iter()
+            .map(|(name, type_, value)| json!({
+                "name": name,
+                "type": type_,
+                "value": match type_ {
+                    "int" => json!(value.parse::<u64>().unwrap_or(0)),
+                    _ => json!(value),
+                },
+            }))
+            .collect();
+
+        let map = json!({
+            "type": "map",
+            "version": TILED_VERSION,
+            "orientation": "orthogonal",
+            "renderorder": "right-down",
+            "width": self.width,
+            "height": self.height,
+            "tilewidth": SECTOR_EDGE,
+            "tileheight": SECTOR_EDGE,
+            "infinite": false,
+            "nextlayerid": layers.len() + 1,
+            "nextobjectid": 1,
+            "properties": properties,
+            "tilesets": tilesets,
+            "layers": layers,
+        });
+
+        let file = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(file, &map).map_err(io::Error::from)?;
+        Ok(())
+    }
+
+    /// Writes map in Tiled TMX format, layers are CSV encoded.
+    pub fn write_tmx(&self, path: &Path) -> Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        let layers = self.layers();
+
+        writeln!(file, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(
+            file,
+            r#"<map version="{}" orientation="orthogonal" renderorder="right-down" width="{}" height="{}" tilewidth="{}" tileheight="{}" infinite="0" nextlayerid="{}" nextobjectid="1">"#,
+            TILED_VERSION,
+            self.width,
+            self.height,
+            SECTOR_EDGE,
+            SECTOR_EDGE,
+            layers.len() + 1
+        )?;
+
+        writeln!(file, " <properties>")?;
+        for (name, type_, value) in self.properties() {
+            writeln!(
+                file,
+                r#"  <property name="{}" type="{}" value="{}"/>"#,
+                name, type_, value
+            )?;
+        }
+        writeln!(file, " </properties>")?;
+
+        for tileset in self.tilesets() {
+            writeln!(
+                file,
+                r#" <tileset firstgid="{}" name="{}" tilewidth="{}" tileheight="{}" tilecount="{}" columns="{}">"#,
+                tileset.first_gid,
+                tileset.name,
+                SECTOR_EDGE,
+                SECTOR_EDGE,
+                tileset.tile_count,
+                tileset.columns()
+            )?;
+            writeln!(
+                file,
+                r#"  <image source="{}" width="{}" height="{}"/>"#,
+                tileset.image,
+                tileset.image_width(),
+                tileset.image_height()
+            )?;
+            for (id, name) in &tileset.names {
+                writeln!(file, r#"  <tile id="{}">"#, id)?;
+                writeln!(file, "   <properties>")?;
+                writeln!(
+                    file,
+                    r#"    <property name="name" value="{}"/>"#,
+                    name
+                )?;
+                writeln!(file, "   </properties>")?;
+                writeln!(file, "  </tile>")?;
+            }
+            writeln!(file, " </tileset>")?;
+        }
+
+        for (i, (name, data)) in layers.iter().enumerate() {
+            writeln!(
+                file,
+                r#" <layer id="{}" name="{}" width="{}" height="{}">"#,
+                i + 1, name, self.width, self.height
+            )?;
+            writeln!(file, r#"  <data encoding="csv">"#)?;
+            // Row per line, every row but last ends with comma
+            let rows: Vec<String> = data.chunks(self.width.max(1) as usize)
+                .map(|row| {
+                    let row: Vec<String> = row.iter()
+                        .map(|gid| gid.to_string())
+                        .collect();
+                    row.join(",")
+                })
+                .collect();
+            writeln!(file, "{}", rows.join(",\n"))?;
+            writeln!(file, "  </data>")?;
+            writeln!(file, " </layer>")?;
+        }
+
+        writeln!(file, "</map>")?;
+        file.flush()?;
+        Ok(())
+    }
+
+    fn tileset(&self) -> Tileset {
+        Tileset {
+            name: "tiles",
+            image: TILESET_FILE,
+            first_gid: 1,
+            tile_count: self.tiles.len() as u32,
+            names: Vec::new(),
+        }
+    }
+
+    fn types_tileset(&self) -> Tileset {
+        Tileset {
+            name: "types",
+            image: TYPES_TILESET_FILE,
+            // Types tileset follows map tileset
+            first_gid: self.tiles.len() as u32 + 1,
+            tile_count: SECTOR_TYPES.len() as u32,
+            names: SECTOR_TYPES.iter()
+                .map(|type_| (type_.value() as u32, type_.name()))
+                .collect(),
+        }
+    }
+
+    fn tilesets(&self) -> Vec<Tileset> {
+        vec![self.tileset(), self.types_tileset()]
+    }
+
+    /// Global tile ids of map and typemap layers, 0 is empty tile.
+    fn layers(&self) -> Vec<(&'static str, Vec<u32>)> {
+        let tiles_gid = self.tileset().first_gid;
+        let map: Vec<u32> = self.sectors.iter()
+            .map(|sector| sector.map(|tile| tiles_gid + tile).unwrap_or(0))
+            .collect();
+
+        // Unknown types are left empty
+        let types_gid = self.types_tileset().first_gid;
+        let types: Vec<u32> = self.types.iter()
+            .map(|type_| match type_ {
+                SectorType::Unknown(_) => 0,
+                type_ => types_gid + type_.value() as u32,
+            })
+            .collect();
+
+        vec![("map", map), ("types", types)]
+    }
+
+    /// Heightmap as name, type and value of map properties.
+    ///
+    /// Heights of sector corners are stored row after row, comma separated.
+    fn properties(&self) -> Vec<(&'static str, &'static str, String)> {
+        let heights: Vec<String> = self.heights.iter()
+            .map(|height| height.to_string())
+            .collect();
+        let min = self.heights.iter().cloned().min().unwrap_or(0);
+        let max = self.heights.iter().cloned().max().unwrap_or(0);
+
+        vec![
+            ("heightmap", "string", heights.join(",")),
+            ("heightmap_width", "int", (self.width + 1).to_string()),
+            ("heightmap_height", "int", (self.height + 1).to_string()),
+            ("heightmap_min", "int", min.to_string()),
+            ("heightmap_max", "int", max.to_string()),
+        ]
+    }
+}
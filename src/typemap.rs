@@ -0,0 +1,322 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use image::{ImageBuffer, Rgb, RgbImage};
+use serde::Serialize;
+
+use super::error::{Error, ErrorKind, Result};
+use super::world::{SectorType, World, SECTOR_TYPES};
+
+/// Typemap files name, without extension
+pub const TYPEMAP_NAME: &str = "typemap";
+/// Image explaining typemap colors
+pub const LEGEND_FILE: &str = "legend.png";
+
+// Legend layout, in pixels
+const SWATCH_EDGE: u32 = 16;
+const LEGEND_MARGIN: u32 = 4;
+const GLYPH_WIDTH: u32 = 3;
+const GLYPH_HEIGHT: u32 = 5;
+const GLYPH_SCALE: u32 = 2;
+const LEGEND_BACKGROUND: [u8; 3] = [255, 255, 255];
+const LEGEND_TEXT: [u8; 3] = [0, 0, 0];
+// Color of sectors with type that has no color
+const MISSING_COLOR: [u8; 3] = [0, 0, 0];
+
+/// Colors sector types are drawn with in typemap.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TypeColors {
+    colors: Vec<Option<[u8; 3]>>,
+}
+
+impl Default for TypeColors {
+    fn default() -> TypeColors {
+        let mut colors = TypeColors { colors: vec![None; 256] };
+        colors.set(SectorType::Grass, [49, 97, 8]);
+        colors.set(SectorType::Water, [24, 73, 107]);
+        colors.set(SectorType::Shore, [82, 158, 206]);
+        colors.set(SectorType::Block, [206, 32, 0]);
+        colors.set(SectorType::Slower, [189, 186, 16]);
+        colors.set(SectorType::Slowest, [231, 142, 24]);
+        colors
+    }
+}
+
+impl TypeColors {
+    /// Returns color of type, unknown types have none unless set.
+    pub fn get(&self, type_: SectorType) -> Option<[u8; 3]> {
+        self.colors[type_.value() as usize]
+    }
+
+    pub fn set(&mut self, type_: SectorType, color: [u8; 3]) {
+        self.colors[type_.value() as usize] = Some(color);
+    }
+
+    /// Returns type drawn with color, preferring known types.
+    pub fn find(&self, color: [u8; 3]) -> Option<SectorType> {
+        let known = SECTOR_TYPES.iter().cloned()
+            .find(|type_| self.get(*type_) == Some(color));
+        known.or_else(|| {
+            (0..=255u8)
+                .map(SectorType::from_value)
+                .find(|type_| self.get(*type_) == Some(color))
+        })
+    }
+
+    /// Parses "TYPE=COLOR" assignment, eg. "water=#0000ff" or "9=ff00ff".
+    pub fn parse_assignment(value: &str) -> Result<(SectorType, [u8; 3])> {
+        let invalid = || {
+            let message = format!(
+                "\"{}\" is not a TYPE=COLOR sector type color", value
+            );
+            Error::new(ErrorKind::InvalidArgument(message))
+        };
+
+        let mut parts = value.splitn(2, '=');
+        let type_ = parts.next().and_then(SectorType::parse);
+        let color = parts.next().and_then(parse_color);
+        match (type_, color) {
+            (Some(type_), Some(color)) => Ok((type_, color)),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// Type entry of typemap legend.
+#[derive(Clone, Debug, Serialize)]
+pub struct LegendEntry {
+    pub value: u8,
+    pub name: SectorType,
+    /// Color as "#rrggbb", if type has one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+    /// Number of sectors of this type
+    pub count: usize,
+}
+
+/// Typemap saved as JSON.
+#[derive(Clone, Debug, Serialize)]
+pub struct TypemapGrid {
+    pub width: u32,
+    pub height: u32,
+    /// Type value of every sector, row after row
+    pub types: Vec<Vec<u8>>,
+    pub legend: Vec<LegendEntry>,
+}
+
+/// Saves typemap as PNG, CSV and JSON, and legend image.
+///
+/// Returns warnings about unknown sector types found on map.
+pub fn render_typemap(
+    world: &World, colors: &TypeColors, path: &Path
+) -> Result<Vec<String>> {
+    let legend = get_legend(world, colors);
+
+    let mut path = path.join(TYPEMAP_NAME);
+    path.set_extension("png");
+    if !path.is_file() {
+        typemap_image(world, colors).save(&path)?;
+    }
+
+    path.set_extension("csv");
+    if !path.is_file() {
+        write_csv(world, &path)?;
+    }
+
+    path.set_extension("json");
+    if !path.is_file() {
+        let grid = TypemapGrid {
+            width: world.width,
+            height: world.height,
+            types: rows(world),
+            legend: legend.clone(),
+        };
+        let file = BufWriter::new(File::create(&path)?);
+        serde_json::to_writer_pretty(file, &grid).map_err(io::Error::from)?;
+    }
+
+    path.set_file_name(LEGEND_FILE);
+    if !path.is_file() {
+        legend_image(&legend, colors).save(&path)?;
+    }
+
+    let warnings = legend.iter()
+        .filter(|entry| entry.name.is_unknown() && entry.count > 0)
+        .map(|entry| {
+            let mut message = format!(
+                "unknown sector type {} in {} sectors",
+                entry.value, entry.count
+            );
+            if entry.color.is_none() {
+                message.push_str(", drawn black in typemap");
+            }
+            message
+        })
+        .collect();
+
+    Ok(warnings)
+}
+
+/// Draws every sector as single pixel of its type's color.
+pub fn typemap_image(world: &World, colors: &TypeColors) -> RgbImage {
+    let mut img: RgbImage = ImageBuffer::new(world.width, world.height);
+    for (x, y, pixel) in img.enumerate_pixels_mut() {
+        let type_ = world.types[(x + y * world.width) as usize];
+        *pixel = Rgb(colors.get(type_).unwrap_or(MISSING_COLOR));
+    }
+    img
+}
+
+/// Lists known types, followed by unknown ones found on map.
+fn get_legend(world: &World, colors: &TypeColors) -> Vec<LegendEntry> {
+    let mut counts = [0usize; 256];
+    for type_ in &world.types {
+        counts[type_.value() as usize] += 1;
+    }
+
+    (0..=255u8)
+        .map(SectorType::from_value)
+        .filter(|type_| {
+            SECTOR_TYPES.contains(type_) || counts[type_.value() as usize] > 0
+        })
+        .map(|type_| LegendEntry {
+            value: type_.value(),
+            name: type_,
+            color: colors.get(type_).map(|[r, g, b]| {
+                format!("#{:02x}{:02x}{:02x}", r, g, b)
+            }),
+            count: counts[type_.value() as usize],
+        })
+        .collect()
+}
+
+fn rows(world: &World) -> Vec<Vec<u8>> {
+    world.types.chunks(world.width.max(1) as usize)
+        .map(|row| row.iter().map(|type_| type_.value()).collect())
+        .collect()
+}
+
+/// Writes type values, row per line.
+fn write_csv(world: &World, path: &Path) -> Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    for row in rows(world) {
+        let row: Vec<String> = row.iter().map(|v| v.to_string()).collect();
+        writeln!(file, "{}", row.join(","))?;
+    }
+    file.flush()?;
+    Ok(())
+}
+
+/// Draws color swatch with type value and name for every legend entry.
+fn legend_image(legend: &[LegendEntry], colors: &TypeColors) -> RgbImage {
+    let labels: Vec<String> = legend.iter()
+        .map(|entry| format!("{} {}", entry.value, entry.name))
+        .collect();
+    let longest = labels.iter().map(|label| label.len()).max().unwrap_or(0);
+
+    let glyph_advance = (GLYPH_WIDTH + 1) * GLYPH_SCALE;
+    let row_height = SWATCH_EDGE + LEGEND_MARGIN;
+    let text_left = LEGEND_MARGIN * 2 + SWATCH_EDGE;
+    let width = text_left + longest as u32 * glyph_advance + LEGEND_MARGIN;
+    let height = LEGEND_MARGIN + legend.len() as u32 * row_height;
+
+    let mut img: RgbImage = ImageBuffer::from_pixel(
+        width, height, Rgb(LEGEND_BACKGROUND)
+    );
+    for (i, (entry, label)) in legend.iter().zip(labels.iter()).enumerate() {
+        let top = LEGEND_MARGIN + i as u32 * row_height;
+        let color = colors.get(entry.name).unwrap_or(MISSING_COLOR);
+        for y in top..top + SWATCH_EDGE {
+            for x in LEGEND_MARGIN..LEGEND_MARGIN + SWATCH_EDGE {
+                img.put_pixel(x, y, Rgb(color));
+            }
+        }
+
+        // Center text vertically on swatch
+        let text_top = top + (SWATCH_EDGE - GLYPH_HEIGHT * GLYPH_SCALE) / 2;
+        for (j, character) in label.chars().enumerate() {
+            let left = text_left + j as u32 * glyph_advance;
+            draw_glyph(&mut img, character, left, text_top);
+        }
+    }
+    img
+}
+
+fn draw_glyph(img: &mut RgbImage, character: char, left: u32, top: u32) {
+    let rows = glyph(character);
+    for (y, row) in rows.iter().enumerate() {
+        for x in 0..GLYPH_WIDTH {
+            // Leftmost pixel is highest of 3 bits
+            if row & (1 << (GLYPH_WIDTH - 1 - x)) == 0 {
+                continue;
+            }
+            for dy in 0..GLYPH_SCALE {
+                for dx in 0..GLYPH_SCALE {
+                    img.put_pixel(
+                        left + x * GLYPH_SCALE + dx,
+                        top + y as u32 * GLYPH_SCALE + dy,
+                        Rgb(LEGEND_TEXT)
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Returns 3x5 pixels glyph, row after row, letters are drawn uppercase.
+fn glyph(character: char) -> [u8; 5] {
+    match character.to_ascii_lowercase() {
+        'a' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'b' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'c' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'd' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'e' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'f' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'g' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'h' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'i' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'j' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'k' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'l' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'm' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'n' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'o' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'p' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'r' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        's' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        't' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'u' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'v' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'w' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'x' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b110, 0b001, 0b010, 0b100, 0b111],
+        '3' => [0b110, 0b001, 0b010, 0b001, 0b110],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b110, 0b001, 0b110],
+        '6' => [0b011, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b010, 0b010, 0b010],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b110],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        _ => [0; 5],
+    }
+}
+
+/// Parses "#rrggbb" or "rrggbb" color.
+fn parse_color(value: &str) -> Option<[u8; 3]> {
+    let value = value.trim_start_matches('#');
+    if value.len() != 6 || !value.is_ascii() {
+        return None;
+    }
+
+    let mut color = [0u8; 3];
+    for (i, channel) in color.iter_mut().enumerate() {
+        *channel = u8::from_str_radix(&value[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(color)
+}
@@ -1,26 +1,33 @@
-use std::error::Error;
-use std::io::Cursor;
+use std::io::{Cursor, Read, Seek};
 use byteorder::{LittleEndian, ReadBytesExt};
 
-pub fn buf_to_le_i32(buf: &[u8]) -> Result<i32, Box<dyn Error>> {
+use super::error::{Error, Result};
+
+pub fn buf_to_le_i32(buf: &[u8]) -> Result<i32> {
     if buf.len() == 2 {
         let value = Cursor::new(buf).read_i16::<LittleEndian>()?;
         return Ok(value as i32);
     }
-    
+
     Ok(Cursor::new(buf).read_i32::<LittleEndian>()?)
 }
 
-pub fn buf_to_le_u32(buf: &[u8]) -> Result<u32, Box<dyn Error>> {
+pub fn buf_to_le_u32(buf: &[u8]) -> Result<u32> {
     if buf.len() == 2 {
         let value = Cursor::new(buf).read_u16::<LittleEndian>()?;
         return Ok(value as u32);
     }
-        
+
     Ok(Cursor::new(buf).read_u32::<LittleEndian>()?)
 }
 
-pub fn buf_to_le_u64(buf: &[u8]) -> Result<u64, Box<dyn Error>> {
+pub fn buf_to_le_u64(buf: &[u8]) -> Result<u64> {
     let value = buf_to_le_u32(buf)?;
     Ok(value as u64)
-}
\ No newline at end of file
+}
+
+/// Fills buffer from reader, reporting offset at which reading failed.
+pub fn read_buf<R: Read + Seek>(reader: &mut R, buf: &mut [u8]) -> Result<()> {
+    let offset = reader.stream_position()?;
+    reader.read_exact(buf).map_err(|error| Error::from(error).at(offset))
+}
@@ -1,23 +1,49 @@
-use std::error;
 use std::fs::{File, create_dir_all};
-use std::io::{Error, ErrorKind, Read, Seek, SeekFrom};
-use std::path::PathBuf;
-use std::str;
+use std::io;
+use std::io::{BufWriter, Read, Seek, Write};
+use std::path::Path;
 use std::vec::Vec;
+use byteorder::{LittleEndian, WriteBytesExt};
 use image::{ImageBuffer, Rgb, RgbImage};
+use serde::Serialize;
+
+use super::archive::ExtractOptions;
+use super::error::Result;
+use super::indexed::{IndexedImage, Transparency};
+use super::mesh::{TerrainMesh, MESH_NAME};
+use super::pngfile::write_png_gray16;
+use super::tiled::{
+    TiledFormat, TiledMap, TILED_MAP_NAME, TILESET_FILE, TYPES_TILESET_FILE
+};
+use super::typemap::render_typemap;
+use super::world::{World, SECTOR_EDGE};
+
+/// Elevation range of map, written next to heightmap.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct HeightmapStats {
+    /// Number of vertices in row and column, one more than sectors
+    pub width: u32,
+    pub height: u32,
+    pub min: u16,
+    pub max: u16,
+}
 
-use super::utils::buf_to_le_u32;
-
-const WLD_FILE_HEADER: &str = "WLD";
-const INVALID_HEADER_ERROR: &str = "Opened file is not a valid WLD package";
-const SECTOR_EDGE: i64 = 32;
-
+/// Extracts map to directory named after WLD file.
+///
+/// Returns warnings about map data that was extracted anyway.
 pub fn extract_wld(
-    wld_file: &mut PathBuf, path: &mut PathBuf
-) -> Result<bool, Box<dyn error::Error>> {
+    wld_file: &Path, path: &Path, options: &ExtractOptions
+) -> Result<Vec<String>> {
     // Create dst dir named after file
     let mut path = path.to_path_buf();
-    let dst_dirname = wld_file.file_stem().unwrap();
+    let dst_dirname = match wld_file.file_stem() {
+        Some(dst_dirname) => dst_dirname,
+        None => {
+            let message = "WLD path doesn't point to a file";
+            let error = io::Error::new(io::ErrorKind::InvalidInput, message);
+            return Err(error.into());
+        },
+    };
     path.push(dst_dirname);
     if !path.is_dir() {
         create_dir_all(&path)?;
@@ -25,137 +51,101 @@ pub fn extract_wld(
 
     // Open wld file
     let mut wld_file = File::open(wld_file)?;
-    check_wld_file_header(&mut wld_file)?;
-
-    // Skip next two bytes
-    wld_file.seek(SeekFrom::Current(2))?;
-
-    // Next four bytes is map width x height
-    let mut header = [0;4];
-    wld_file.read(&mut header)?;
-    let width = buf_to_le_u32(&header[0..2])?;
-    let height = buf_to_le_u32(&header[2..4])?;
-
-    // Calculate data length
-    let length = (width * height) as usize;
-
-    // Next length bytes is minimap
-    let mut minimap = vec![0u8; length];
-    wld_file.read(&mut minimap)?;
-
-    // Next length * 2 bytes is map data chunk order
-    let mut chunk_order: Vec<u32> = Vec::new();
-    // Zerofill chunk order
-    for _ in 0..length {
-        chunk_order.push(0);
-    }
-    // Read chunk ordering from file
-    for i in 0..length {
-        let mut chunk = [0;2];
-        wld_file.read(&mut chunk)?;
-        let chunk = buf_to_le_u32(&chunk)? as usize;
-        if chunk < length {
-            chunk_order[chunk] = i as u32;
-        }
-    }
+    extract_wld_data(&mut wld_file, &path, options)
+}
 
-    // Heightmap is not using palette, so it can be rendered in place
-    let heightmap_len = ((width + 1) * (height + 1) * 2) as i64;
-    if !render_heightmap(width, height, &mut wld_file, &path)? {
-        // If we didn't read heightmap, skip its data
-        wld_file.seek(SeekFrom::Current(heightmap_len))?;
-    }
+pub fn extract_wld_data<R: Read + Seek>(
+    wld_file: &mut R, path: &Path, options: &ExtractOptions
+) -> Result<Vec<String>> {
+    let world = World::from_reader(wld_file)?;
+    let (width, height) = (world.width, world.height);
 
-    // Height map is followed by 2 bytes of grid length
-    // It always equals width * height
-    let mut grid_length = [0;2];
-    wld_file.read(&mut grid_length)?;
-    let grid_length = buf_to_le_u32(&grid_length)? as i64;
-
-    // Calculate map data length
-    let map_length = grid_length * SECTOR_EDGE * SECTOR_EDGE;
-    // And skip it (for now)
-    wld_file.seek(SeekFrom::Current(map_length))?;
-
-    // Map data is followed by palette data (3 * 256 bytes)
-    let palette_length = 256 * 3;
-    let mut palette = vec![0u8; palette_length];
-    wld_file.read(&mut palette)?;
-
-    // Render palette and minimap
-    render_palette(&palette, &path)?;
-    render_minimap(width, height, &minimap, &palette, &path)?;
-
-    // Seek back to map data, and render it
-    wld_file.seek(SeekFrom::Current(palette_length as i64 * -1))?;
-    wld_file.seek(SeekFrom::Current(map_length * -1))?;
-    if !render_map(width, height, &chunk_order, &mut wld_file, &palette, &path)? {
-        // If we didn't read the map, skip its data
-        wld_file.seek(SeekFrom::Current(map_length))?;
+    render_heightmap(width, height, &world.heights, path, options)?;
+    if options.mesh {
+        render_mesh(width, height, &world.heights, path)?;
     }
-
-    // After rendering map data, skip palette and render sector types
-    wld_file.seek(SeekFrom::Current(palette_length as i64))?;
-    render_sector_types(width, height, &mut wld_file, &path)?;
-
-    Ok(true)
-}
-
-fn check_wld_file_header(
-    wld_file: &mut File
-) -> Result<(), Box<dyn error::Error>> {
-    // First 4 bytes should be "WLD" string
-    let mut buffer = [0; 3];
-    wld_file.read(&mut buffer)?;
-
-    let header = str::from_utf8(&buffer)?;
-    if header != WLD_FILE_HEADER {
-        let err = Error::new(ErrorKind::InvalidData, INVALID_HEADER_ERROR);
-        return Err(Box::new(err));
+    render_palette(&world.palette, path)?;
+    render_minimap(&world, path, options)?;
+    render_map(&world, path, options)?;
+    let warnings = render_typemap(&world, &options.type_colors, path)?;
+    if let Some(format) = options.tiled {
+        let map = TiledMap::new(&world);
+        render_tiled_map(&map, format, &world.palette, path, options)?;
     }
 
-    Ok(())
+    Ok(warnings)
 }
 
+/// Saves heights as 16-bit PNG, stats as JSON and optionally raw `.r16`.
 fn render_heightmap(
-    width: u32, height: u32, wld_file: &mut File, path: &PathBuf
-) -> Result<bool, Box<dyn error::Error>> {
+    width: u32,
+    height: u32,
+    heights: &[u16],
+    path: &Path,
+    options: &ExtractOptions
+) -> Result<bool> {
+    // Height map uses vertexes, so it adds extra data
+    let width = width + 1;
+    let height = height + 1;
+
+    let mut rendered = false;
     let mut path = path.to_path_buf();
     path.push("heightmap");
-    path.set_extension("png");
 
-    if path.is_file() {
-        return Ok(false);
+    path.set_extension("png");
+    if !path.is_file() {
+        let mut file = BufWriter::new(File::create(&path)?);
+        write_png_gray16(&mut file, width, height, heights)?;
+        rendered = true;
     }
 
-    // Height map uses vertexes, so it adds extra data
-    let width = width + 1;
-    let height = height + 1;
+    path.set_extension("json");
+    if !path.is_file() {
+        let stats = HeightmapStats {
+            width,
+            height,
+            min: heights.iter().cloned().min().unwrap_or(0),
+            max: heights.iter().cloned().max().unwrap_or(0),
+        };
+        let file = BufWriter::new(File::create(&path)?);
+        serde_json::to_writer_pretty(file, &stats).map_err(io::Error::from)?;
+        rendered = true;
+    }
 
-    // Read image from wld file
-    let mut img: RgbImage = ImageBuffer::new(width, height);
-    for (_x, _y, pixel) in img.enumerate_pixels_mut() {
-        // Read vertex height
-        let mut vertex = [0;2];
-        wld_file.read(&mut vertex)?;
-        let mut vertex = buf_to_le_u32(&vertex)?;
-        // Height can be bigger than 255, but we are limiting that
-        // So we can use simple gradient presentation
-        if vertex > 255 {
-            vertex = 255;
+    path.set_extension("r16");
+    if options.raw_heightmap && !path.is_file() {
+        // Raw heights, row after row, as 2 bytes little endian numbers
+        let mut file = BufWriter::new(File::create(&path)?);
+        for vertex in heights {
+            file.write_u16::<LittleEndian>(*vertex)?;
         }
-        let vertex = vertex as u8;
-        *pixel = Rgb([vertex, vertex, vertex]);
+        file.flush()?;
+        rendered = true;
     }
 
-    img.save(path)?;
+    Ok(rendered)
+}
+
+/// Saves terrain as OBJ and glTF meshes, textured with map render.
+fn render_mesh(
+    width: u32, height: u32, heights: &[u16], path: &Path
+) -> Result<bool> {
+    let obj_path = path.join(format!("{}.obj", MESH_NAME));
+    let gltf_path = path.join(format!("{}.gltf", MESH_NAME));
+    if obj_path.is_file() && gltf_path.is_file() {
+        return Ok(false);
+    }
+
+    let mesh = TerrainMesh::new(width, height, heights);
+    mesh.write_obj(path, MESH_NAME)?;
+    mesh.write_gltf(path, MESH_NAME)?;
 
     Ok(true)
 }
 
 fn render_palette(
-    palette: &Vec<u8>, path: &PathBuf
-) -> Result<bool, Box<dyn error::Error>> {
+    palette: &[u8], path: &Path
+) -> Result<bool> {
     let mut path = path.to_path_buf();
     path.push("palette");
     path.set_extension("png");
@@ -182,12 +172,8 @@ fn render_palette(
 }
 
 fn render_minimap(
-    width: u32,
-    height: u32,
-    minimap: &Vec<u8>,
-    palette: &Vec<u8>,
-    path: &PathBuf
-) -> Result<bool, Box<dyn error::Error>> {
+    world: &World, path: &Path, options: &ExtractOptions
+) -> Result<bool> {
     let mut path = path.to_path_buf();
     path.push("minimap");
     path.set_extension("png");
@@ -196,31 +182,18 @@ fn render_minimap(
         return Ok(false);
     }
 
-    let mut img: RgbImage = ImageBuffer::new(width, height);
-    for (x, y, pixel) in img.enumerate_pixels_mut() {
-        let color = (x + (y * width)) as usize;
-        let color = minimap[color] as usize;
-        let color = [
-            palette[color * 3],
-            palette[(color * 3) + 1],
-            palette[(color * 3) + 2]
-        ];
-        *pixel = Rgb(color);
-    }
-
-    img.save(path)?;
+    // Minimap is one palette index per sector
+    let img = wld_image(
+        world.width, world.height, world.minimap.clone(), &world.palette
+    );
+    img.save_as(&path, options.indexed)?;
 
     Ok(true)
 }
 
 fn render_map(
-    width: u32,
-    height: u32,
-    chunk_order: &Vec<u32>,
-    wld_file: &mut File,
-    palette: &Vec<u8>,
-    path: &PathBuf
-) -> Result<bool, Box<dyn error::Error>> {
+    world: &World, path: &Path, options: &ExtractOptions
+) -> Result<bool> {
     let mut path = path.to_path_buf();
     path.push("full");
     path.set_extension("png");
@@ -229,108 +202,77 @@ fn render_map(
         return Ok(false);
     }
 
-    let sector = SECTOR_EDGE as u32;
-    let sector_length = (sector * sector) as usize;
-    let width_px = width * sector;
-    let height_px = height * sector;
-
-    let mut img: RgbImage = ImageBuffer::new(width_px, height_px);
-
-    for chunk in chunk_order {
-        // Calculate chunk coords
-        let chunk_y = chunk / height;
-        let chunk_x = chunk % width;
-
-        // Read chunk data
-        let mut data = vec![0u8; sector_length];
-        wld_file.read(&mut data)?;
-
-        // Place them on map
-        for y in 0..sector {
-            let y_abs = y + (chunk_y * sector);
-            for x in 0..sector {
-                let x_abs = x + (chunk_x * sector);
-
-                let offset = (x + (y * sector)) as usize;
-                let color = data[offset] as usize;
-                let color = [
-                    palette[color * 3],
-                    palette[(color * 3) + 1],
-                    palette[(color * 3) + 2]
-                ];
-
-                let pixel = img.get_pixel_mut(x_abs as u32, y_abs as u32);
-                *pixel = Rgb(color);
+    let sector = SECTOR_EDGE;
+    let width_px = world.pixel_width();
+    let height_px = world.pixel_height();
+
+    let mut pixels = vec![0u8; (width_px * height_px) as usize];
+
+    for chunk_y in 0..world.height {
+        for chunk_x in 0..world.width {
+            // Sectors using missing tiles are left blank
+            let data = match world.sector_tile(chunk_x, chunk_y) {
+                Some(data) => data,
+                None => continue,
+            };
+
+            // Place tile rows on map
+            for (y, row) in data.chunks(sector as usize).enumerate() {
+                let y_abs = y as u32 + (chunk_y * sector);
+                let start = ((chunk_x * sector) + (y_abs * width_px)) as usize;
+                pixels[start..start + row.len()].copy_from_slice(row);
             }
         }
     }
 
-    img.save(path)?;
+    let img = wld_image(width_px, height_px, pixels, &world.palette);
+    img.save_as(&path, options.indexed)?;
 
     Ok(true)
 }
 
-const TYPE_GRASS: u8 = 0;
-const TYPE_WATER: u8 = 1;
-const TYPE_SHORE: u8 = 2;
-const TYPE_BLOCK: u8 = 3;
-const TYPE_SLOWER: u8 = 4;
-const TYPE_SLOWST: u8 = 5;
-
-fn render_sector_types(
-    width: u32,
-    height: u32,
-    wld_file: &mut File,
-    path: &PathBuf
-) -> Result<bool, Box<dyn error::Error>> {
-    let mut path = path.to_path_buf();
-    path.push("typemap");
-    path.set_extension("png");
+fn wld_image(
+    width: u32, height: u32, pixels: Vec<u8>, palette: &[u8; 768]
+) -> IndexedImage {
+    IndexedImage {
+        width,
+        height,
+        origin_x: 0,
+        origin_y: 0,
+        pixels,
+        palette_id: None,
+        palette: *palette,
+        transparency: Transparency::None,
+    }
+}
 
-    if path.is_file() {
+/// Saves deduplicated tileset, types tileset and Tiled map using them.
+fn render_tiled_map(
+    map: &TiledMap,
+    format: TiledFormat,
+    palette: &[u8; 768],
+    path: &Path,
+    options: &ExtractOptions
+) -> Result<bool> {
+    let map_path = path.join(TILED_MAP_NAME).with_extension(format.extension());
+    if map_path.is_file() {
         return Ok(false);
     }
 
-    let width_half = (width / 2) as u32;
-    let height_half = (height / 2) as u32;
-    let quarter = (width_half * height_half) as usize;
-    
-    let mut img: RgbImage = ImageBuffer::new(width, height);
-
-    // Typemap is split in four quarters
-    for y_quarter in 0..2 {
-        for x_quarter in 0..2 {
-            let mut data = vec![0u8; quarter];
-            wld_file.read(&mut data)?;
-
-            for y in 0..height_half {
-                for x in 0..width_half {
-                    let mut x = x;
-                    let mut y = y;
-
-                    let src = (x + (y * height_half)) as usize;
-                    let type_ = data[src];
-
-                    if x_quarter == 1 { x += width_half; }
-                    if y_quarter == 1 { y += height_half; }
-
-                    let pixel = img.get_pixel_mut(x, y);
-
-                    match type_ {
-                        TYPE_GRASS => *pixel = Rgb([49, 97, 8]),
-                        TYPE_WATER => *pixel = Rgb([24, 73, 107]),
-                        TYPE_SHORE => *pixel = Rgb([82, 158, 206]),
-                        TYPE_BLOCK => *pixel = Rgb([206, 32, 0]),
-                        TYPE_SLOWER => *pixel = Rgb([189, 186, 16]),
-                        TYPE_SLOWST => *pixel = Rgb([231, 142, 24]),
-                        _ => println!("type: {}", type_),
-                    }
-                }
-            }
-        }   
+    let tileset_path = path.join(TILESET_FILE);
+    if !tileset_path.is_file() {
+        let img = map.tileset_image(palette);
+        img.save_as(&tileset_path, options.indexed)?;
+    }
+    let types_path = path.join(TYPES_TILESET_FILE);
+    if !types_path.is_file() {
+        map.types_image(&options.type_colors).save(&types_path)?;
     }
 
-    img.save(path)?;
+    match format {
+        TiledFormat::Tmx => map.write_tmx(&map_path)?,
+        TiledFormat::Json => map.write_json(&map_path)?,
+    }
 
     Ok(true)
-}
\ No newline at end of file
+}
@@ -0,0 +1,257 @@
+use std::collections::HashMap;
+use std::fs::{read, read_to_string, File};
+use std::path::Path;
+use image::RgbImage;
+use png::{BitDepth, ColorType, Decoder, HasParameters, Transformations};
+
+use super::error::{Error, ErrorKind, Result};
+use super::quantize::{nearest_index, quantize, remap};
+use super::typemap::TypeColors;
+use super::world::{SectorType, World, SECTOR_EDGE, TILE_LENGTH};
+
+/// Builds map from image of whole map, like `full.png` render.
+///
+/// Image size has to be multiple of 32 pixels, every 32x32 pixels square
+/// becomes sector, identical squares share tile. Colors are mapped to
+/// `palette`, or palette is made from image colors if it's not given.
+/// `heights` has height of every vertex and `types` type of every sector,
+/// row after row. `unknown` bytes follow file header, like `World::unknown`
+/// of map being rebuilt or `DEFAULT_UNKNOWN`.
+pub fn build_world(
+    map: &RgbImage,
+    palette: Option<&[u8; 768]>,
+    heights: Vec<u16>,
+    types: Vec<SectorType>,
+    unknown: [u8; 2]
+) -> Result<World> {
+    let (width_px, height_px) = map.dimensions();
+    let aligned = width_px % SECTOR_EDGE == 0
+        && height_px % SECTOR_EDGE == 0;
+    if !aligned {
+        return Err(invalid(format!(
+            "map image size {}x{} is not multiple of {} pixels",
+            width_px, height_px, SECTOR_EDGE
+        )));
+    }
+    let width = width_px / SECTOR_EDGE;
+    let height = height_px / SECTOR_EDGE;
+
+    let colors: Vec<[u8; 3]> = map.pixels()
+        .map(|pixel| [pixel[0], pixel[1], pixel[2]])
+        .collect();
+    let (palette, pixels) = match palette {
+        Some(palette) => (*palette, remap(&colors, palette)),
+        None => quantize(&colors),
+    };
+
+    // Rows of pixels are regrouped into 32x32 tiles, sectors drawn the
+    // same way share single tile
+    let sectors = (width * height) as usize;
+    let mut unique: HashMap<[u8; TILE_LENGTH], u16> = HashMap::new();
+    let mut tiles: Vec<u8> = Vec::new();
+    let mut tile_ids: Vec<u16> = Vec::with_capacity(sectors);
+    let mut minimap: Vec<u8> = Vec::with_capacity(sectors);
+    for sector_y in 0..height {
+        for sector_x in 0..width {
+            let mut tile = [0u8; TILE_LENGTH];
+            let mut sums = [0u32; 3];
+            let rows = tile.chunks_mut(SECTOR_EDGE as usize);
+            for (y, row) in (0..SECTOR_EDGE).zip(rows) {
+                let top = (sector_y * SECTOR_EDGE + y) * width_px;
+                let start = (top + sector_x * SECTOR_EDGE) as usize;
+                let end = start + SECTOR_EDGE as usize;
+                row.copy_from_slice(&pixels[start..end]);
+                for color in &colors[start..end] {
+                    for (sum, value) in sums.iter_mut().zip(color.iter()) {
+                        *sum += *value as u32;
+                    }
+                }
+            }
+
+            // Tile count is stored in 2 bytes
+            let tile_id = match unique.get(&tile) {
+                Some(tile_id) => *tile_id,
+                None if unique.len() < u16::MAX as usize => {
+                    let tile_id = unique.len() as u16;
+                    tiles.extend_from_slice(&tile);
+                    unique.insert(tile, tile_id);
+                    tile_id
+                },
+                None => return Err(invalid(format!(
+                    "map image has more than {} unique tiles", u16::MAX
+                ))),
+            };
+            tile_ids.push(tile_id);
+
+            // Minimap shows average color of sector
+            let mut average = [0u8; 3];
+            for (value, sum) in average.iter_mut().zip(sums.iter()) {
+                *value = (sum / TILE_LENGTH as u32) as u8;
+            }
+            minimap.push(nearest_index(&palette, average));
+        }
+    }
+
+    let world = World {
+        unknown,
+        width,
+        height,
+        minimap,
+        tile_ids,
+        tiles,
+        heights,
+        palette,
+        types,
+    };
+
+    // Report wrong heights or types now, not when map is saved
+    world.check_lengths()?;
+
+    Ok(world)
+}
+
+/// Reads map image, dropping its alpha channel.
+pub fn read_map_image(path: &Path) -> Result<RgbImage> {
+    Ok(image::open(path)?.to_rgb())
+}
+
+/// Reads height of every vertex of map `width` x `height` sectors.
+///
+/// File is 16-bit or 8-bit grayscale PNG, or raw `.r16` file with 2 bytes
+/// little endian heights.
+pub fn read_heightmap(
+    path: &Path, width: u32, height: u32
+) -> Result<Vec<u16>> {
+    let columns = width + 1;
+    let rows = height + 1;
+    let length = (columns * rows) as usize;
+
+    let is_raw = path.extension()
+        .map(|extension| extension.eq_ignore_ascii_case("r16"))
+        .unwrap_or(false);
+    if is_raw {
+        let data = read(path)?;
+        if data.len() != length * 2 {
+            return Err(invalid(format!(
+                "heightmap {} has {} bytes, expected {} for {}x{} vertices",
+                path.display(), data.len(), length * 2, columns, rows
+            )));
+        }
+        return Ok(data.chunks(2)
+            .map(|vertex| u16::from_le_bytes([vertex[0], vertex[1]]))
+            .collect());
+    }
+
+    // Keep 16-bit samples as they are
+    let mut decoder = Decoder::new(File::open(path)?);
+    decoder.set(Transformations::IDENTITY);
+    let (info, mut reader) = decoder.read_info().map_err(png_error)?;
+    if (info.width, info.height) != (columns, rows) {
+        return Err(invalid(format!(
+            "heightmap {} is {}x{}, expected {}x{} vertices",
+            path.display(), info.width, info.height, columns, rows
+        )));
+    }
+    let mut data = vec![0u8; info.buffer_size()];
+    reader.next_frame(&mut data).map_err(png_error)?;
+
+    match (info.color_type, info.bit_depth) {
+        (ColorType::Grayscale, BitDepth::Sixteen) => {
+            // PNG samples are big endian
+            Ok(data.chunks(2)
+                .map(|vertex| u16::from_be_bytes([vertex[0], vertex[1]]))
+                .collect())
+        },
+        (ColorType::Grayscale, BitDepth::Eight) => {
+            Ok(data.iter().map(|vertex| *vertex as u16).collect())
+        },
+        _ => Err(invalid(format!(
+            "heightmap {} is not 8-bit or 16-bit grayscale PNG",
+            path.display()
+        ))),
+    }
+}
+
+/// Reads type of every sector of map `width` x `height` sectors.
+///
+/// File is CSV with type value or name of every sector, row per line,
+/// or image with sectors drawn in their type colors.
+pub fn read_typemap(
+    path: &Path, width: u32, height: u32, colors: &TypeColors
+) -> Result<Vec<SectorType>> {
+    let is_csv = path.extension()
+        .map(|extension| extension.eq_ignore_ascii_case("csv"))
+        .unwrap_or(false);
+    let types = if is_csv {
+        read_typemap_csv(path, width)?
+    } else {
+        read_typemap_image(path, width, colors)?
+    };
+
+    if types.len() != (width * height) as usize {
+        return Err(invalid(format!(
+            "typemap {} has {} sectors, expected {}x{}",
+            path.display(), types.len(), width, height
+        )));
+    }
+
+    Ok(types)
+}
+
+fn read_typemap_csv(path: &Path, width: u32) -> Result<Vec<SectorType>> {
+    let mut types: Vec<SectorType> = Vec::new();
+    let lines = read_to_string(path)?;
+    let rows = lines.lines().filter(|line| !line.trim().is_empty());
+    for (y, row) in rows.enumerate() {
+        let row: Vec<&str> = row.split(',').map(str::trim).collect();
+        if row.len() != width as usize {
+            return Err(invalid(format!(
+                "typemap {} row {} has {} sectors, expected {}",
+                path.display(), y + 1, row.len(), width
+            )));
+        }
+        for value in row {
+            match SectorType::parse(value) {
+                Some(type_) => types.push(type_),
+                None => return Err(invalid(format!(
+                    "typemap {} row {}: \"{}\" is not a sector type",
+                    path.display(), y + 1, value
+                ))),
+            }
+        }
+    }
+    Ok(types)
+}
+
+fn read_typemap_image(
+    path: &Path, width: u32, colors: &TypeColors
+) -> Result<Vec<SectorType>> {
+    let img = image::open(path)?.to_rgb();
+    if img.width() != width {
+        return Err(invalid(format!(
+            "typemap {} is {} pixels wide, expected {}",
+            path.display(), img.width(), width
+        )));
+    }
+
+    let mut types: Vec<SectorType> = Vec::new();
+    for (x, y, pixel) in img.enumerate_pixels() {
+        let color = [pixel[0], pixel[1], pixel[2]];
+        match colors.find(color) {
+            Some(type_) => types.push(type_),
+            None => return Err(invalid(format!(
+                "typemap {} pixel {}x{} has color of no sector type",
+                path.display(), x, y
+            ))),
+        }
+    }
+    Ok(types)
+}
+
+fn invalid(message: String) -> Error {
+    Error::new(ErrorKind::InvalidArgument(message))
+}
+
+fn png_error(error: png::DecodingError) -> Error {
+    Error::from(std::io::Error::from(error))
+}
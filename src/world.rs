@@ -0,0 +1,521 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::result;
+use serde::{Serialize, Serializer};
+
+use super::error::{Error, ErrorKind, Result};
+use super::utils::{buf_to_le_u32, read_buf};
+
+/// Width and height of sector in map pixels.
+pub const SECTOR_EDGE: u32 = 32;
+/// Number of pixels in single tile.
+pub const TILE_LENGTH: usize = (SECTOR_EDGE * SECTOR_EDGE) as usize;
+
+/// Bytes following file header in maps not rebuilt from existing one.
+///
+/// Their meaning is unknown and the value wasn't taken from game maps, it
+/// only has to be some. Maps keep their own bytes in `World::unknown`.
+pub const DEFAULT_UNKNOWN: [u8; 2] = [1, 0];
+
+const WLD_FILE_HEADER: &str = "WLD";
+const INVALID_HEADER_ERROR: &str = "Opened file is not a valid WLD package";
+
+/// Terrain type of sector, stored in typemap as single byte.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum SectorType {
+    /// Land passable by every unit (type 0)
+    Grass,
+    /// Deep water (type 1)
+    Water,
+    /// Shallow water along coast (type 2)
+    Shore,
+    /// Impassable terrain (type 3)
+    Block,
+    /// Rough terrain slowing units down (type 4)
+    Slower,
+    /// Very rough terrain (type 5)
+    Slowest,
+    Unknown(u8),
+}
+
+/// Every known sector type, ordered by value.
+pub const SECTOR_TYPES: [SectorType; 6] = [
+    SectorType::Grass,
+    SectorType::Water,
+    SectorType::Shore,
+    SectorType::Block,
+    SectorType::Slower,
+    SectorType::Slowest,
+];
+
+impl SectorType {
+    pub fn from_value(value: u8) -> SectorType {
+        match value {
+            0 => SectorType::Grass,
+            1 => SectorType::Water,
+            2 => SectorType::Shore,
+            3 => SectorType::Block,
+            4 => SectorType::Slower,
+            5 => SectorType::Slowest,
+            _ => SectorType::Unknown(value),
+        }
+    }
+
+    /// Returns byte used for type in the typemap.
+    pub fn value(self) -> u8 {
+        match self {
+            SectorType::Grass => 0,
+            SectorType::Water => 1,
+            SectorType::Shore => 2,
+            SectorType::Block => 3,
+            SectorType::Slower => 4,
+            SectorType::Slowest => 5,
+            SectorType::Unknown(value) => value,
+        }
+    }
+
+    /// Returns readable name.
+    pub fn name(self) -> String {
+        let name = match self {
+            SectorType::Grass => "grass",
+            SectorType::Water => "water",
+            SectorType::Shore => "shore",
+            SectorType::Block => "block",
+            SectorType::Slower => "slower",
+            SectorType::Slowest => "slowest",
+            SectorType::Unknown(value) => return format!("unknown-{}", value),
+        };
+        String::from(name)
+    }
+
+    /// Parses readable name or type value.
+    pub fn parse(value: &str) -> Option<SectorType> {
+        if let Ok(value) = value.parse::<u8>() {
+            return Some(SectorType::from_value(value));
+        }
+
+        let value = value.to_lowercase();
+        SECTOR_TYPES.iter().cloned().find(|type_| type_.name() == value)
+    }
+
+    pub fn is_unknown(self) -> bool {
+        matches!(self, SectorType::Unknown(_))
+    }
+}
+
+impl Serialize for SectorType {
+    fn serialize<S: Serializer>(
+        &self, serializer: S
+    ) -> result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.name())
+    }
+}
+
+impl fmt::Display for SectorType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Map read from WLD file.
+///
+/// Map is grid of `width` x `height` sectors, every sector is drawn with
+/// 32x32 pixels tile. Heights are stored for sector corners (vertices),
+/// so there is one more vertex than sectors in every row and column.
+///
+/// Pixel coordinates refer to map render, where sector (x, y) covers
+/// pixels from (x * 32, y * 32) to (x * 32 + 31, y * 32 + 31), and vertex
+/// (x, y) lies at pixel (x * 32, y * 32).
+#[derive(Clone, Debug)]
+pub struct World {
+    /// Bytes of unknown meaning following file header
+    pub unknown: [u8; 2],
+    /// Map size in sectors
+    pub width: u32,
+    pub height: u32,
+    /// Palette index of every sector, row after row
+    pub minimap: Vec<u8>,
+    /// Tile every sector is drawn with, row after row
+    pub tile_ids: Vec<u16>,
+    /// Palette indices of all tiles, 32 * 32 per tile
+    pub tiles: Vec<u8>,
+    /// Height of every vertex, row after row
+    pub heights: Vec<u16>,
+    /// 3 * 256 bytes of RGB colors
+    pub palette: [u8; 768],
+    /// Type of every sector, row after row
+    pub types: Vec<SectorType>,
+}
+
+impl World {
+    /// Opens WLD file and reads whole map.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<World> {
+        let mut wld_file = BufReader::new(File::open(path)?);
+        World::from_reader(&mut wld_file)
+    }
+
+    /// Reads WLD map from any seekable source.
+    pub fn from_reader<R: Read + Seek>(wld_file: &mut R) -> Result<World> {
+        check_wld_file_header(wld_file)?;
+
+        // Next two bytes are unknown
+        let mut unknown = [0; 2];
+        read_buf(wld_file, &mut unknown)?;
+
+        // Next four bytes is map width x height
+        let mut header = [0;4];
+        read_buf(wld_file, &mut header)?;
+        let width = buf_to_le_u32(&header[0..2])?;
+        let height = buf_to_le_u32(&header[2..4])?;
+
+        // Calculate data length
+        let length = (width * height) as usize;
+
+        // Next length bytes is minimap
+        let mut minimap = vec![0u8; length];
+        read_buf(wld_file, &mut minimap)?;
+
+        // Next length * 2 bytes is tile of every sector
+        let mut data = vec![0u8; length * 2];
+        read_buf(wld_file, &mut data)?;
+        let tile_ids = data.chunks(2)
+            .map(|tile| u16::from_le_bytes([tile[0], tile[1]]))
+            .collect();
+
+        let heights = read_heights(width, height, wld_file)?;
+
+        // Height map is followed by 2 bytes of grid length
+        // It always equals width * height
+        let mut grid_length = [0;2];
+        read_buf(wld_file, &mut grid_length)?;
+        let grid_length = buf_to_le_u32(&grid_length)? as usize;
+
+        // Map data is grid of 32x32 pixels tiles
+        let mut tiles = vec![0u8; grid_length * TILE_LENGTH];
+        read_buf(wld_file, &mut tiles)?;
+
+        // Map data is followed by palette data (3 * 256 bytes)
+        let mut palette = [0u8; 768];
+        read_buf(wld_file, &mut palette)?;
+
+        // Palette is followed by sector types
+        let types = read_sector_types(width, height, wld_file)?;
+
+        Ok(World {
+            unknown,
+            width,
+            height,
+            minimap,
+            tile_ids,
+            tiles,
+            heights,
+            palette,
+            types,
+        })
+    }
+
+    /// Saves map as WLD file.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        self.write(&mut file)?;
+        file.flush()?;
+        Ok(())
+    }
+
+    /// Writes map in WLD format, sections in the same order they're read.
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        self.check_lengths()?;
+
+        writer.write_all(WLD_FILE_HEADER.as_bytes())?;
+        writer.write_all(&self.unknown)?;
+        writer.write_all(&(self.width as u16).to_le_bytes())?;
+        writer.write_all(&(self.height as u16).to_le_bytes())?;
+        writer.write_all(&self.minimap)?;
+        for tile_id in &self.tile_ids {
+            writer.write_all(&tile_id.to_le_bytes())?;
+        }
+        for vertex in &self.heights {
+            writer.write_all(&vertex.to_le_bytes())?;
+        }
+        writer.write_all(&(self.tile_count() as u16).to_le_bytes())?;
+        writer.write_all(&self.tiles)?;
+        writer.write_all(&self.palette)?;
+
+        // Sector types are split in four quarters
+        let widths = typemap_halves(self.width);
+        let heights = typemap_halves(self.height);
+        for y_quarter in 0..2 {
+            for x_quarter in 0..2 {
+                let left = if x_quarter == 1 { widths[0] } else { 0 };
+                let top = if y_quarter == 1 { heights[0] } else { 0 };
+                for y in top..top + heights[y_quarter] {
+                    for x in left..left + widths[x_quarter] {
+                        let type_ = self.types[(x + y * self.width) as usize];
+                        writer.write_all(&[type_.value()])?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Map render size in pixels.
+    pub fn pixel_width(&self) -> u32 {
+        self.width * SECTOR_EDGE
+    }
+
+    pub fn pixel_height(&self) -> u32 {
+        self.height * SECTOR_EDGE
+    }
+
+    /// Number of tiles stored in the file.
+    pub fn tile_count(&self) -> usize {
+        self.tiles.len() / TILE_LENGTH
+    }
+
+    /// Returns palette indices of tile, row after row.
+    pub fn tile(&self, tile_id: u16) -> Option<&[u8]> {
+        let start = tile_id as usize * TILE_LENGTH;
+        self.tiles.get(start..start + TILE_LENGTH)
+    }
+
+    /// Checks if sector coordinates lie on the map.
+    pub fn contains_sector(&self, x: u32, y: u32) -> bool {
+        x < self.width && y < self.height
+    }
+
+    /// Returns id of tile sector is drawn with.
+    pub fn sector_tile_id(&self, x: u32, y: u32) -> Option<u16> {
+        self.sector_index(x, y).map(|i| self.tile_ids[i])
+    }
+
+    /// Returns pixels of tile sector is drawn with.
+    pub fn sector_tile(&self, x: u32, y: u32) -> Option<&[u8]> {
+        self.sector_tile_id(x, y).and_then(|tile_id| self.tile(tile_id))
+    }
+
+    pub fn sector_type(&self, x: u32, y: u32) -> Option<SectorType> {
+        self.sector_index(x, y).map(|i| self.types[i])
+    }
+
+    /// Returns palette index sector has in minimap.
+    pub fn minimap_index(&self, x: u32, y: u32) -> Option<u8> {
+        self.sector_index(x, y).map(|i| self.minimap[i])
+    }
+
+    /// Returns heights of sector corners: top left, top right, bottom left
+    /// and bottom right.
+    pub fn sector_heights(&self, x: u32, y: u32) -> Option<[u16; 4]> {
+        if !self.contains_sector(x, y) {
+            return None;
+        }
+        Some([
+            self.vertex_height(x, y)?,
+            self.vertex_height(x + 1, y)?,
+            self.vertex_height(x, y + 1)?,
+            self.vertex_height(x + 1, y + 1)?,
+        ])
+    }
+
+    pub fn vertex_height(&self, x: u32, y: u32) -> Option<u16> {
+        if x > self.width || y > self.height {
+            return None;
+        }
+        self.heights.get((x + y * (self.width + 1)) as usize).cloned()
+    }
+
+    /// Returns palette index of map render pixel.
+    pub fn pixel(&self, x: u32, y: u32) -> Option<u8> {
+        let (sector_x, sector_y) = self.pixel_to_sector(x, y)?;
+        let tile = self.sector_tile(sector_x, sector_y)?;
+        let offset = (x % SECTOR_EDGE) + (y % SECTOR_EDGE) * SECTOR_EDGE;
+        Some(tile[offset as usize])
+    }
+
+    /// Returns sector containing map render pixel.
+    pub fn pixel_to_sector(&self, x: u32, y: u32) -> Option<(u32, u32)> {
+        let sector = (x / SECTOR_EDGE, y / SECTOR_EDGE);
+        if self.contains_sector(sector.0, sector.1) {
+            Some(sector)
+        } else {
+            None
+        }
+    }
+
+    /// Returns top left pixel of sector in map render.
+    pub fn sector_to_pixel(&self, x: u32, y: u32) -> Option<(u32, u32)> {
+        if self.contains_sector(x, y) {
+            Some((x * SECTOR_EDGE, y * SECTOR_EDGE))
+        } else {
+            None
+        }
+    }
+
+    /// Returns vertex nearest to map render pixel.
+    pub fn pixel_to_vertex(&self, x: u32, y: u32) -> Option<(u32, u32)> {
+        self.pixel_to_sector(x, y)?;
+        let half = SECTOR_EDGE / 2;
+        Some(((x + half) / SECTOR_EDGE, (y + half) / SECTOR_EDGE))
+    }
+
+    /// Returns pixel vertex lies at, it's outside of render for vertices
+    /// on right and bottom map edge.
+    pub fn vertex_to_pixel(&self, x: u32, y: u32) -> Option<(u32, u32)> {
+        if x > self.width || y > self.height {
+            return None;
+        }
+        Some((x * SECTOR_EDGE, y * SECTOR_EDGE))
+    }
+
+    /// Returns top left vertex of sector.
+    pub fn sector_to_vertex(&self, x: u32, y: u32) -> Option<(u32, u32)> {
+        if self.contains_sector(x, y) {
+            Some((x, y))
+        } else {
+            None
+        }
+    }
+
+    /// Returns sector vertex is top left corner of, vertices on right and
+    /// bottom map edge have none.
+    pub fn vertex_to_sector(&self, x: u32, y: u32) -> Option<(u32, u32)> {
+        self.sector_to_vertex(x, y)
+    }
+
+    /// Checks that every section fits map size before it's written.
+    pub(super) fn check_lengths(&self) -> Result<()> {
+        let invalid = |message: String| {
+            Err(Error::new(ErrorKind::InvalidArgument(message)))
+        };
+
+        if self.width > u16::MAX as u32 || self.height > u16::MAX as u32 {
+            return invalid(format!(
+                "map size {}x{} is too large", self.width, self.height
+            ));
+        }
+
+        let sectors = (self.width * self.height) as usize;
+        let vertices = vertex_count(self.width, self.height)?;
+        let sections = [
+            ("minimap", self.minimap.len(), sectors),
+            ("tile ids", self.tile_ids.len(), sectors),
+            ("heights", self.heights.len(), vertices),
+            ("sector types", self.types.len(), sectors),
+        ];
+        for (name, length, expected) in sections.iter() {
+            if length != expected {
+                return invalid(format!(
+                    "map has {} {}, expected {}", length, name, expected
+                ));
+            }
+        }
+
+        if self.tiles.len() % TILE_LENGTH != 0 {
+            return invalid(String::from("tiles data is not 32x32 tiles"));
+        }
+        if self.tile_count() > u16::MAX as usize {
+            return invalid(String::from("map has too many tiles"));
+        }
+
+        Ok(())
+    }
+
+    fn sector_index(&self, x: u32, y: u32) -> Option<usize> {
+        if self.contains_sector(x, y) {
+            Some((x + y * self.width) as usize)
+        } else {
+            None
+        }
+    }
+}
+
+fn check_wld_file_header<R: Read + Seek>(
+    wld_file: &mut R
+) -> Result<()> {
+    // First 3 bytes should be "WLD" string
+    wld_file.seek(SeekFrom::Start(0))?;
+    let mut buffer = [0; 3];
+    read_buf(wld_file, &mut buffer)?;
+
+    if buffer != WLD_FILE_HEADER.as_bytes() {
+        let kind = ErrorKind::InvalidHeader(INVALID_HEADER_ERROR.to_string());
+        return Err(Error::new(kind).at(0));
+    }
+
+    Ok(())
+}
+
+fn size_error() -> Error {
+    let message = String::from("map size doesn't fit memory");
+    Error::new(ErrorKind::InvalidHeader(message)).at(0)
+}
+
+/// Counts height map vertexes, one more than sectors in each dimension.
+fn vertex_count(width: u32, height: u32) -> Result<usize> {
+    let count = (width as u64 + 1).checked_mul(height as u64 + 1)
+        .ok_or_else(size_error)?;
+    if count > usize::MAX as u64 {
+        return Err(size_error());
+    }
+    Ok(count as usize)
+}
+
+/// Reads height of every vertex, row after row.
+fn read_heights<R: Read + Seek>(
+    width: u32, height: u32, wld_file: &mut R
+) -> Result<Vec<u16>> {
+    // Every vertex height takes 2 bytes
+    let length = vertex_count(width, height)?.checked_mul(2)
+        .ok_or_else(size_error)?;
+    let mut data = vec![0u8; length];
+    read_buf(wld_file, &mut data)?;
+
+    Ok(data.chunks(2)
+        .map(|vertex| u16::from_le_bytes([vertex[0], vertex[1]]))
+        .collect())
+}
+
+/// Splits map dimension into typemap halves: first (left or top) one and
+/// second one.
+///
+/// First half gets extra row or column of odd dimension.
+pub fn typemap_halves(size: u32) -> [u32; 2] {
+    [size - size / 2, size / 2]
+}
+
+/// Reads type of every sector, row after row.
+fn read_sector_types<R: Read + Seek>(
+    width: u32, height: u32, wld_file: &mut R
+) -> Result<Vec<SectorType>> {
+    let widths = typemap_halves(width);
+    let heights = typemap_halves(height);
+
+    let mut types = vec![SectorType::Grass; (width * height) as usize];
+
+    // Typemap is split in four quarters, left to right, top to bottom
+    for y_quarter in 0..2 {
+        for x_quarter in 0..2 {
+            let quarter_width = widths[x_quarter];
+            let quarter_height = heights[y_quarter];
+            let mut data = vec![0u8; (quarter_width * quarter_height) as usize];
+            read_buf(wld_file, &mut data)?;
+
+            // Quarter's top left sector
+            let left = if x_quarter == 1 { widths[0] } else { 0 };
+            let top = if y_quarter == 1 { heights[0] } else { 0 };
+
+            for y in 0..quarter_height {
+                for x in 0..quarter_width {
+                    let src = (x + (y * quarter_width)) as usize;
+                    let dst = ((left + x) + (top + y) * width) as usize;
+                    types[dst] = SectorType::from_value(data[src]);
+                }
+            }
+        }
+    }
+
+    Ok(types)
+}
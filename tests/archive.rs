@@ -0,0 +1,74 @@
+use max2::{Archive, AssetKind, ErrorKind};
+
+/// Archive written byte by byte: header, data of two assets, directory
+/// and single palette.
+fn stored_archive() -> Vec<u8> {
+    let mut data: Vec<u8> = Vec::new();
+    data.extend_from_slice(b"RES0");
+    data.extend_from_slice(&[0, 0]);
+    // Directory of two 17 bytes long entries follows 9 bytes of data
+    data.extend_from_slice(&23u32.to_le_bytes());
+    data.extend_from_slice(&34u32.to_le_bytes());
+
+    data.extend_from_slice(b"HELLO");
+    data.extend_from_slice(&[1, 2, 3, 4]);
+
+    let entries = [(14u32, 5u32, 7u32, "TEXT"), (19, 4, 9, "BLOB")];
+    for (offset, length, type_, name) in entries.iter() {
+        data.extend_from_slice(&offset.to_le_bytes());
+        data.extend_from_slice(&length.to_le_bytes());
+        data.extend_from_slice(&type_.to_le_bytes());
+        data.push(name.len() as u8);
+        data.extend_from_slice(name.as_bytes());
+    }
+
+    data.extend_from_slice(&1u16.to_le_bytes());
+    data.extend((0..768).map(|i| (i % 256) as u8));
+    data
+}
+
+#[test]
+fn reads_archive_from_bytes() {
+    let mut archive = Archive::from_bytes(stored_archive()).unwrap();
+    let directory = archive.directory();
+    assert_eq!((directory.offset, directory.length), (23, 34));
+
+    let assets = archive.assets().to_vec();
+    let listed: Vec<_> = assets.iter()
+        .map(|asset| (asset.name.as_str(), asset.kind, asset.offset))
+        .collect();
+    assert_eq!(listed, [
+        ("TEXT", AssetKind::Text, 14),
+        ("BLOB", AssetKind::Unknown(9), 19),
+    ]);
+    assert_eq!(archive.read_stored(&assets[0]).unwrap(), b"HELLO");
+    let blob = archive.read_by_name("BLOB").unwrap();
+    assert_eq!(blob, Some(vec![1, 2, 3, 4]));
+    assert_eq!(archive.read_by_name("NONE").unwrap(), None);
+
+    assert_eq!(archive.palettes().len(), 1);
+    assert_eq!(archive.palettes()[0][257], 1);
+}
+
+#[test]
+fn reads_archive_without_palettes() {
+    let mut data = stored_archive();
+    data.truncate(data.len() - 770);
+    let archive = Archive::from_bytes(data).unwrap();
+    assert_eq!(archive.assets().len(), 2);
+    assert!(archive.palettes().is_empty());
+}
+
+#[test]
+fn rejects_bytes_not_starting_with_header() {
+    let mut data = stored_archive();
+    data[3] = b'1';
+    let error = Archive::from_bytes(data).err().unwrap();
+    assert!(matches!(error.kind, ErrorKind::InvalidHeader(_)));
+    assert_eq!(error.offset, Some(0));
+
+    // Directory listing assets past the data
+    let mut data = stored_archive();
+    data.truncate(40);
+    assert!(Archive::from_bytes(data).is_err());
+}
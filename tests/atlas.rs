@@ -0,0 +1,79 @@
+use max2::atlas::{pack_frames, Rect};
+
+mod common;
+
+use common::frame;
+
+fn rect(x: u32, y: u32, w: u32, h: u32) -> Rect {
+    Rect { x, y, w, h }
+}
+
+#[test]
+fn packs_frames_in_rows_with_padding() {
+    let frames = [frame(4, 3, 1, 1), frame(2, 2, 0, 2), frame(3, 5, -1, 4)];
+    let atlas = pack_frames(&frames);
+
+    // Third frame doesn't fit next to second one in 8 pixels wide row
+    assert_eq!(
+        atlas.rects,
+        [rect(0, 0, 4, 3), rect(5, 0, 2, 2), rect(0, 4, 3, 5)]
+    );
+    assert_eq!((atlas.image.width, atlas.image.height), (7, 9));
+
+    for (frame, rect) in frames.iter().zip(atlas.rects.iter()) {
+        for y in 0..frame.height {
+            for x in 0..frame.width {
+                let (atlas_x, atlas_y) = (rect.x + x, rect.y + y);
+                assert_eq!(
+                    atlas.image.index(atlas_x, atlas_y), frame.index(x, y)
+                );
+                assert_eq!(
+                    atlas.image.is_opaque(atlas_x, atlas_y),
+                    frame.is_opaque(x, y)
+                );
+            }
+        }
+    }
+    // Padding between frames stays transparent
+    assert!((0..3).all(|y| !atlas.image.is_opaque(4, y)));
+}
+
+#[test]
+fn packs_container_without_frames() {
+    let atlas = pack_frames(&[]);
+    assert!(atlas.rects.is_empty());
+    assert_eq!((atlas.image.width, atlas.image.height), (1, 1));
+}
+
+#[test]
+fn describes_frame_rectangles_and_hotspots() {
+    let frames = [frame(4, 3, 1, 1), frame(2, 2, 0, 2), frame(3, 5, -1, 4)];
+    let atlas = pack_frames(&frames);
+    let descriptor = atlas.descriptor(&frames, "TANK.PNG", false);
+
+    assert_eq!(descriptor.frames.len(), 3);
+    for (i, described) in descriptor.frames.iter().enumerate() {
+        let packed = atlas.rects[i];
+        let (w, h) = (packed.w, packed.h);
+        assert_eq!(described.filename, i.to_string());
+        assert_eq!(described.frame, packed);
+        assert_eq!(described.sprite_source_size, rect(0, 0, w, h));
+        assert_eq!(
+            (described.source_size.w, described.source_size.h), (w, h)
+        );
+        assert_eq!(
+            (described.hotspot.x, described.hotspot.y),
+            (frames[i].origin_x, frames[i].origin_y)
+        );
+    }
+    let pivot = descriptor.frames[2].pivot;
+    assert_eq!((pivot.x, pivot.y), (-1.0 / 3.0, 0.8));
+
+    let meta = &descriptor.meta;
+    assert_eq!(meta.image, "TANK.PNG");
+    assert_eq!((meta.size.w, meta.size.h), (7, 9));
+    assert_eq!(meta.format, "RGBA8888");
+
+    let descriptor = atlas.descriptor(&frames, "TANK.PNG", true);
+    assert_eq!(descriptor.meta.format, "INDEXED");
+}
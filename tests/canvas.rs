@@ -0,0 +1,60 @@
+use max2::Transparency;
+use max2::canvas::{align_frames, FrameCanvas};
+
+mod common;
+
+use common::frame;
+
+#[test]
+fn fits_every_frame_around_common_anchor() {
+    // Hotspots outside of frame, on its edge and inside
+    let frames = [frame(4, 2, -3, 1), frame(2, 6, 0, 6), frame(5, 3, 2, 1)];
+    let canvas = FrameCanvas::for_frames(&frames);
+    assert_eq!(canvas, FrameCanvas {
+        width: 9,
+        height: 8,
+        anchor_x: 2,
+        anchor_y: 6,
+    });
+
+    let empty = FrameCanvas::for_frames(&[]);
+    assert_eq!((empty.width, empty.height), (0, 0));
+}
+
+#[test]
+fn places_frame_hotspots_on_anchor() {
+    let frames = [frame(4, 2, -3, 1), frame(2, 6, 0, 6), frame(5, 3, 2, 1)];
+    let (canvas, aligned) = align_frames(&frames);
+
+    assert_eq!(aligned.len(), frames.len());
+    for (frame, placed) in frames.iter().zip(aligned.iter()) {
+        assert_eq!((placed.width, placed.height), (9, 8));
+        assert_eq!(
+            (placed.origin_x, placed.origin_y),
+            (canvas.anchor_x, canvas.anchor_y)
+        );
+
+        // Frame's top left corner lands away from anchor by its hotspot
+        let left = (canvas.anchor_x - frame.origin_x) as u32;
+        let top = (canvas.anchor_y - frame.origin_y) as u32;
+        for y in 0..canvas.height {
+            for x in 0..canvas.width {
+                let inside = x >= left && x < left + frame.width
+                    && y >= top && y < top + frame.height;
+                if inside {
+                    let (frame_x, frame_y) = (x - left, y - top);
+                    assert_eq!(
+                        placed.index(x, y), frame.index(frame_x, frame_y)
+                    );
+                    assert_eq!(
+                        placed.is_opaque(x, y),
+                        frame.is_opaque(frame_x, frame_y)
+                    );
+                } else {
+                    assert!(!placed.is_opaque(x, y));
+                }
+            }
+        }
+        assert!(matches!(placed.transparency, Transparency::Mask(_)));
+    }
+}
@@ -0,0 +1,90 @@
+// Not every test uses every fixture
+#![allow(dead_code)]
+
+use std::env;
+use std::fs::{create_dir_all, remove_dir_all};
+use std::io::Cursor;
+use std::path::PathBuf;
+
+use max2::{Archive, AssetKind, IndexedImage, Transparency};
+use max2::resbuilder::ArchiveBuilder;
+
+pub const PALETTE_ID: usize = 1;
+
+/// Frame with margins and runs too long for single chunk, and empty rows.
+pub fn frame(
+    width: u32, height: u32, origin_x: i32, origin_y: i32
+) -> IndexedImage {
+    let mut pixels: Vec<u8> = Vec::new();
+    let mut mask: Vec<bool> = Vec::new();
+    for y in 0..height {
+        for x in 0..width {
+            let opaque = match y % 4 {
+                0 => false,
+                1 => x >= 300,
+                2 => x < 520 || x % 7 == 0,
+                _ => (x / 3 + y) % 2 == 0,
+            };
+            pixels.push(if opaque { (x * 5 + y) as u8 } else { 0 });
+            mask.push(opaque);
+        }
+    }
+
+    IndexedImage {
+        width,
+        height,
+        origin_x,
+        origin_y,
+        pixels,
+        palette_id: Some(PALETTE_ID),
+        palette: palette(),
+        transparency: Transparency::Mask(mask),
+    }
+}
+
+/// Opaque image without palette id.
+pub fn image(width: u32, height: u32, pixels: Vec<u8>) -> IndexedImage {
+    IndexedImage {
+        width,
+        height,
+        origin_x: 0,
+        origin_y: 0,
+        pixels,
+        palette_id: None,
+        palette: palette(),
+        transparency: Transparency::None,
+    }
+}
+
+pub fn palette() -> [u8; 768] {
+    let mut palette = [0u8; 768];
+    for (i, value) in palette.iter_mut().enumerate() {
+        *value = (i / 3) as u8;
+    }
+    palette
+}
+
+/// Archive with palettes and single asset.
+pub fn archive(
+    name: &str, kind: AssetKind, data: Vec<u8>
+) -> Archive<Cursor<Vec<u8>>> {
+    let mut builder = ArchiveBuilder::new();
+    builder.add_palette([0; 768]);
+    builder.add_palette(palette());
+    builder.add(name, kind, data);
+
+    let mut res: Vec<u8> = Vec::new();
+    builder.write(&mut res).unwrap();
+    Archive::from_bytes(res).unwrap()
+}
+
+/// Empty directory unique for test process.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir()
+        .join(format!("max2-{}-{}", name, std::process::id()));
+    if dir.is_dir() {
+        remove_dir_all(&dir).unwrap();
+    }
+    create_dir_all(&dir).unwrap();
+    dir
+}
@@ -0,0 +1,182 @@
+use std::fs::remove_dir_all;
+use image::{ImageBuffer, Rgba, RgbaImage};
+
+use max2::{AssetKind, ErrorKind, ExtractOptions, IndexedImage, Transparency};
+use max2::imgcontainer::encode_img_container;
+use max2::imgmonocontainer::encode_img_mono_container;
+
+mod common;
+
+use common::{archive, frame, temp_dir, PALETTE_ID};
+
+#[test]
+fn decodes_encoded_frames() {
+    let frames = [
+        frame(600, 9, 300, -4),
+        frame(1, 1, 0, 0),
+        frame(3, 0, 1, 1),
+    ];
+    let data = encode_img_container(&frames, PALETTE_ID).unwrap();
+    let mut archive = archive("UNIT", AssetKind::SpriteContainer, data);
+    let asset = archive.asset("UNIT").unwrap().clone();
+    let decoded = archive.decode_frames(&asset).unwrap();
+
+    assert_eq!(decoded.len(), frames.len());
+    for (frame, decoded) in frames.iter().zip(decoded.iter()) {
+        assert_eq!(
+            (decoded.width, decoded.height),
+            (frame.width, frame.height)
+        );
+        assert_eq!(
+            (decoded.origin_x, decoded.origin_y),
+            (frame.origin_x, frame.origin_y)
+        );
+        assert_eq!(decoded.palette_id, Some(PALETTE_ID));
+        assert_eq!(decoded.transparency, frame.transparency);
+        assert_eq!(decoded.pixels, frame.pixels);
+    }
+}
+
+#[test]
+fn encodes_decoded_container_to_stored_data() {
+    let frames = [frame(600, 9, 300, -4), frame(40, 5, -2, 7)];
+    let data = encode_img_container(&frames, PALETTE_ID).unwrap();
+    let mut archive = archive("UNIT", AssetKind::SpriteContainer, data.clone());
+    let asset = archive.asset("UNIT").unwrap().clone();
+
+    assert_eq!(archive.reencode(&asset).unwrap(), data);
+    assert_eq!(archive.read_stored(&asset).unwrap(), data);
+}
+
+/// Container written byte by byte the way game containers are laid out:
+/// frames follow offsets list, rows follow row offsets, and every row ends
+/// with 255 byte, also when it reaches frame width.
+fn stored_container() -> Vec<u8> {
+    let mut data: Vec<u8> = Vec::new();
+    let words = |data: &mut Vec<u8>, values: &[u16]| {
+        for value in values {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+    };
+    let offsets = |data: &mut Vec<u8>, values: &[u32]| {
+        for value in values {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+    };
+
+    words(&mut data, &[2, PALETTE_ID as u16]);
+    offsets(&mut data, &[12, 45]);
+
+    // 4x3 frame centered at 1x2: "..##", "####", "...."
+    words(&mut data, &[4, 3, 1, 2]);
+    offsets(&mut data, &[32, 37, 44]);
+    data.extend_from_slice(&[2, 2, 10, 11, 255]);
+    data.extend_from_slice(&[0, 4, 20, 21, 22, 23, 255]);
+    data.push(255);
+
+    // 300x1 frame with only last pixel opaque, margin is split in two
+    words(&mut data, &[300, 1, 0, 0]);
+    offsets(&mut data, &[57]);
+    data.extend_from_slice(&[254, 0, 45, 1, 7, 255]);
+
+    data
+}
+
+#[test]
+fn encodes_container_as_stored() {
+    let data = stored_container();
+    let mut archive = archive("UNIT", AssetKind::SpriteContainer, data.clone());
+    let asset = archive.asset("UNIT").unwrap().clone();
+
+    let frames = archive.decode_frames(&asset).unwrap();
+    assert_eq!(frames[0].pixels, [0, 0, 10, 11, 20, 21, 22, 23, 0, 0, 0, 0]);
+    assert_eq!(frames[1].index(299, 0), 7);
+    assert_eq!(archive.reencode(&asset).unwrap(), data);
+}
+
+#[test]
+fn encodes_empty_container() {
+    let data = encode_img_container(&[], PALETTE_ID).unwrap();
+    assert_eq!(data, [0, 0, PALETTE_ID as u8, 0]);
+
+    let mut archive = archive("UNIT", AssetKind::SpriteContainer, data.clone());
+    let asset = archive.asset("UNIT").unwrap().clone();
+    assert_eq!(archive.reencode(&asset).unwrap(), data);
+}
+
+#[test]
+fn rejects_frames_with_missing_pixels() {
+    let mut short = frame(40, 5, 0, 0);
+    short.pixels.truncate(199);
+    let error = encode_img_container(&[short], PALETTE_ID).unwrap_err();
+    assert!(matches!(error.kind, ErrorKind::InvalidArgument(_)));
+}
+
+#[test]
+fn decodes_encoded_shadows() {
+    let frames = [frame(600, 9, 300, -4), frame(40, 5, -2, 7)];
+    let data = encode_img_mono_container(&frames).unwrap();
+    let mut archive = archive("UNIT", AssetKind::ShadowContainer, data.clone());
+    let asset = archive.asset("UNIT").unwrap().clone();
+    let decoded = archive.decode_frames(&asset).unwrap();
+
+    assert_eq!(decoded.len(), frames.len());
+    for (frame, decoded) in frames.iter().zip(decoded.iter()) {
+        assert_eq!(
+            (decoded.origin_x, decoded.origin_y),
+            (frame.origin_x, frame.origin_y)
+        );
+        assert_eq!(decoded.transparency, frame.transparency);
+    }
+    assert_eq!(archive.reencode(&asset).unwrap(), data);
+}
+
+#[test]
+fn rejects_shadows_with_short_mask() {
+    let mut short = frame(40, 5, 0, 0);
+    if let Transparency::Mask(mask) = &mut short.transparency {
+        mask.truncate(150);
+    }
+    let error = encode_img_mono_container(&[short]).unwrap_err();
+    assert!(matches!(error.kind, ErrorKind::InvalidArgument(_)));
+
+    // Shadows store no colors, so masked frames need no pixels
+    let mut masked = frame(40, 5, 0, 0);
+    masked.pixels.clear();
+    assert!(encode_img_mono_container(&[masked]).is_ok());
+}
+
+#[test]
+fn masks_shadows_by_alpha_threshold() {
+    let alphas = [0u8, 63, 64, 200, 255];
+    let img: RgbaImage = ImageBuffer::from_fn(5, 1, |x, _| {
+        Rgba([255, 255, 255, alphas[x as usize]])
+    });
+
+    let shadow = IndexedImage::mask_from_alpha(&img, 64);
+    let mask = vec![false, false, true, true, true];
+    assert_eq!(shadow.transparency, Transparency::Mask(mask));
+
+    let shadow = IndexedImage::mask_from_alpha(&img, 255);
+    let mask = vec![false, false, false, false, true];
+    assert_eq!(shadow.transparency, Transparency::Mask(mask));
+}
+
+#[test]
+fn skips_atlas_already_extracted() {
+    let dir = temp_dir("atlas");
+    let frames = [frame(4, 3, 1, 1), frame(2, 2, 0, 2)];
+    let data = encode_img_container(&frames, PALETTE_ID).unwrap();
+    let mut archive = archive("UNIT", AssetKind::SpriteContainer, data);
+    let asset = archive.asset("UNIT").unwrap().clone();
+    let options = ExtractOptions { atlas: true, ..Default::default() };
+
+    let mut path = dir.clone();
+    assert!(archive.extract(&asset, &mut path, &options).unwrap());
+    assert!(dir.join("UNIT.PNG").is_file());
+    assert!(dir.join("UNIT.json").is_file());
+    let mut path = dir.clone();
+    assert!(!archive.extract(&asset, &mut path, &options).unwrap());
+
+    remove_dir_all(&dir).unwrap();
+}
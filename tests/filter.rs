@@ -0,0 +1,93 @@
+use max2::{Asset, AssetFilter, AssetKind, ErrorKind, TypeSelector};
+
+fn asset(name: &str, kind: AssetKind) -> Asset {
+    Asset { offset: 14, length: 1, kind, name: String::from(name) }
+}
+
+fn selected(filter: &AssetFilter) -> Vec<String> {
+    let assets = [
+        asset("TANK", AssetKind::SpriteContainer),
+        asset("TANKSHD", AssetKind::ShadowContainer),
+        asset("TANKICON", AssetKind::Image),
+        asset("MAINMENU", AssetKind::ImageWithPalette),
+        asset("MYSTERY", AssetKind::Unknown(9)),
+    ];
+    assets.iter()
+        .filter(|asset| filter.matches(asset))
+        .map(|asset| asset.name.clone())
+        .collect()
+}
+
+fn type_(value: &str) -> TypeSelector {
+    TypeSelector::parse(value).unwrap()
+}
+
+#[test]
+fn parses_unknown_prefix_only_for_unnamed_kinds() {
+    assert_eq!(AssetKind::parse("unknown-9"), Some(AssetKind::Unknown(9)));
+    assert_eq!(AssetKind::parse("9"), Some(AssetKind::Unknown(9)));
+    assert_eq!(AssetKind::parse("5"), Some(AssetKind::Image));
+    assert_eq!(AssetKind::parse("image"), Some(AssetKind::Image));
+    assert_eq!(AssetKind::parse("unknown-5"), None);
+    assert_eq!(AssetKind::parse("unknown-"), None);
+}
+
+#[test]
+fn parses_type_selectors() {
+    assert_eq!(type_("2"), TypeSelector::Kind(AssetKind::SpriteContainer));
+    assert_eq!(type_("shadow-container"), type_("3"));
+    assert_eq!(type_("UNKNOWN"), TypeSelector::Unknown);
+
+    let error = TypeSelector::parse("sprites").unwrap_err();
+    assert!(matches!(error.kind, ErrorKind::InvalidFilter(_)));
+}
+
+#[test]
+fn matches_everything_without_patterns() {
+    assert_eq!(selected(&AssetFilter::new()).len(), 5);
+}
+
+#[test]
+fn matches_names_by_any_pattern() {
+    // Globs ignore case, regular expressions don't
+    let filter = AssetFilter::new().name_glob("tank*").unwrap();
+    assert_eq!(selected(&filter), ["TANK", "TANKSHD", "TANKICON"]);
+
+    let filter = AssetFilter::new()
+        .name_regex("^TANK(SHD)?$").unwrap()
+        .name_glob("main?enu").unwrap();
+    assert_eq!(selected(&filter), ["TANK", "TANKSHD", "MAINMENU"]);
+
+    let filter = AssetFilter::new().name_regex("^tank$").unwrap();
+    assert!(selected(&filter).is_empty());
+}
+
+#[test]
+fn matches_included_types_without_excluded_ones() {
+    let filter = AssetFilter::new()
+        .include_type(type_("2"))
+        .include_type(type_("unknown"));
+    assert_eq!(selected(&filter), ["TANK", "MYSTERY"]);
+
+    let filter = AssetFilter::new()
+        .name_glob("TANK*").unwrap()
+        .exclude_type(type_("sprite-container"))
+        .exclude_type(type_("5"));
+    assert_eq!(selected(&filter), ["TANKSHD"]);
+}
+
+#[test]
+fn matches_archive_names() {
+    let filter = AssetFilter::new().source("*.res").unwrap();
+    assert!(filter.matches_source("MAX2.RES"));
+    assert!(!filter.matches_source("MAX2.CAF"));
+    assert!(AssetFilter::new().matches_source("MAX2.CAF"));
+}
+
+#[test]
+fn rejects_malformed_patterns() {
+    let error = AssetFilter::new().name_glob("TANK[").unwrap_err();
+    assert!(matches!(error.kind, ErrorKind::InvalidFilter(_)));
+    let error = AssetFilter::new().name_regex("(TANK").unwrap_err();
+    assert!(matches!(error.kind, ErrorKind::InvalidFilter(_)));
+}
@@ -0,0 +1,165 @@
+use max2::{Archive, AssetKind, ErrorKind};
+use max2::img::encode_img;
+use max2::imgwithpalette::encode_img_with_palette;
+use max2::resbuilder::ArchiveBuilder;
+
+mod common;
+
+use common::{archive, image, PALETTE_ID};
+
+const KIND: AssetKind = AssetKind::ImageWithPalette;
+const UNKNOWN: [u8; 4] = [1, 2, 3, 4];
+// Unknown bytes, width, height and palette
+const HEADER_LEN: usize = 4 + 2 + 2 + 768;
+
+/// Pixels mixing noise with runs of different lengths.
+fn pixels(length: usize, seed: u32) -> Vec<u8> {
+    let mut state = seed;
+    let mut pixels: Vec<u8> = Vec::new();
+    while pixels.len() < length {
+        state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+        let value = (state >> 16) as u8;
+        let repeat = match state >> 29 {
+            0 => 2,
+            1 => 3,
+            2 => 1 + (state >> 24) as usize % 6,
+            _ => 1,
+        };
+        for _ in 0..repeat.min(length - pixels.len()) {
+            pixels.push(value % 4);
+        }
+    }
+    pixels
+}
+
+/// Fewest bytes chunks can store pixels in, checking every split.
+fn fewest_bytes(pixels: &[u8]) -> usize {
+    let length = pixels.len();
+    let mut costs = vec![usize::MAX; length + 1];
+    costs[length] = 0;
+    for start in (0..length).rev() {
+        for end in start + 1..=length {
+            let literal = 2 + (end - start) + costs[end];
+            costs[start] = costs[start].min(literal);
+            if pixels[start..end].iter().all(|pixel| *pixel == pixels[start]) {
+                costs[start] = costs[start].min(3 + costs[end]);
+            }
+        }
+    }
+    costs[0]
+}
+
+#[test]
+fn decodes_encoded_image() {
+    let image = image(24, 10, pixels(240, 7));
+    let data = encode_img_with_palette(&image, UNKNOWN).unwrap();
+    assert_eq!(&data[..4], &UNKNOWN);
+
+    let mut archive = archive("SPLASH", KIND, data.clone());
+    let asset = archive.asset("SPLASH").unwrap().clone();
+    let decoded = archive.decode_image(&asset).unwrap();
+    assert_eq!((decoded.width, decoded.height), (24, 10));
+    assert_eq!(&decoded.palette[..], &image.palette[..]);
+    assert_eq!(decoded.pixels, image.pixels);
+
+    let info = archive.describe(&asset).unwrap();
+    assert_eq!(info.unknown, Some(UNKNOWN));
+    assert_eq!(archive.reencode(&asset).unwrap(), data);
+}
+
+#[test]
+fn checks_decompressed_pixels_against_image_size() {
+    let image = image(2, 2, vec![1, 2, 3, 4]);
+    let mut data = encode_img_with_palette(&image, UNKNOWN).unwrap();
+
+    // Header of 1x2 image, pixels of 2x2 one
+    data[4] = 1;
+    let mut narrow = archive("SPLASH", KIND, data.clone());
+    let asset = narrow.asset("SPLASH").unwrap().clone();
+    assert_eq!(narrow.decode_image(&asset).unwrap().pixels, [1, 2]);
+
+    // Header of 2x3 image
+    data[4] = 2;
+    data[6] = 3;
+    let mut tall = archive("SPLASH", KIND, data);
+    let asset = tall.asset("SPLASH").unwrap().clone();
+    let error = tall.decode_image(&asset).unwrap_err();
+    assert!(matches!(error.kind, ErrorKind::InvalidRle(_)));
+    assert_eq!(error.offset, Some(asset.offset + HEADER_LEN as u64));
+}
+
+#[test]
+fn compresses_into_fewest_bytes() {
+    for seed in 0..40 {
+        let length = 1 + seed as usize;
+        let pixels = pixels(length, seed);
+        let image = image(length as u32, 1, pixels.clone());
+        let data = encode_img_with_palette(&image, UNKNOWN).unwrap();
+        assert_eq!(
+            data.len() - HEADER_LEN,
+            fewest_bytes(&pixels),
+            "pixels {:?}",
+            pixels
+        );
+    }
+}
+
+#[test]
+fn splits_chunks_longer_than_word() {
+    // Run too long for single repeat, then literal too long for one chunk
+    let mut pixels = vec![9u8; 40_000];
+    pixels.extend((0..40_000u32).map(|i| (i % 200) as u8));
+    let image = image(400, 200, pixels.clone());
+    let data = encode_img_with_palette(&image, UNKNOWN).unwrap();
+
+    let mut archive = archive("SPLASH", KIND, data);
+    let asset = archive.asset("SPLASH").unwrap().clone();
+    assert_eq!(archive.decode_image(&asset).unwrap().pixels, pixels);
+}
+
+#[test]
+fn reports_image_larger_than_asset() {
+    // 4x4 image header followed by only 15 pixels, then next asset
+    let mut data: Vec<u8> = vec![4, 0, 4, 0, 0, 0, 0, 0, 0, 0];
+    data.extend_from_slice(&[1; 15]);
+    let mut builder = ArchiveBuilder::new();
+    builder.add_palette([0; 768]);
+    builder.add("ICON", AssetKind::Image, data);
+    builder.add("NEXT", AssetKind::Image, vec![0; 10]);
+    let mut res: Vec<u8> = Vec::new();
+    builder.write(&mut res).unwrap();
+
+    let mut archive = Archive::from_bytes(res).unwrap();
+    let asset = archive.asset("ICON").unwrap().clone();
+    let error = archive.decode_image(&asset).unwrap_err();
+    assert!(matches!(error.kind, ErrorKind::Truncated));
+}
+
+#[test]
+fn rejects_pixels_not_matching_image_size() {
+    let mut icon = image(4, 4, vec![1; 16]);
+    assert_eq!(encode_img(&icon).unwrap().len(), 10 + 16);
+
+    icon.pixels.push(1);
+    let error = encode_img(&icon).unwrap_err();
+    assert!(matches!(error.kind, ErrorKind::InvalidArgument(_)));
+}
+
+#[test]
+fn keeps_hotspot_and_palette_of_replaced_image() {
+    let mut icon = image(2, 2, vec![1, 2, 3, 4]);
+    icon.origin_x = -3;
+    icon.origin_y = 5;
+    icon.palette_id = Some(PALETTE_ID);
+    let data = encode_img(&icon).unwrap();
+    let mut archive = archive("ICON", AssetKind::Image, data);
+
+    // New image comes without hotspot and palette id, eg. from PNG file
+    let replacement = image(3, 1, vec![7, 8, 9]);
+    let asset = archive.replace_image("ICON", &replacement).unwrap();
+    let replaced = archive.decode_image(&asset).unwrap();
+    assert_eq!((replaced.width, replaced.height), (3, 1));
+    assert_eq!((replaced.origin_x, replaced.origin_y), (-3, 5));
+    assert_eq!(replaced.palette_id, Some(PALETTE_ID));
+    assert_eq!(replaced.pixels, [7, 8, 9]);
+}
@@ -0,0 +1,68 @@
+use std::fs::{remove_dir_all, File};
+
+use serde_json::{json, Value};
+
+use max2::{AssetKind, Manifest};
+use max2::img::encode_img;
+use max2::imgcontainer::encode_img_container;
+
+mod common;
+
+use common::{archive, frame, image, temp_dir, PALETTE_ID};
+
+#[test]
+fn writes_asset_metadata_and_reads_files_back() {
+    let dir = temp_dir("manifest");
+    let frames = [frame(40, 5, -2, 7), frame(3, 2, 1, 0)];
+    let data = encode_img_container(&frames, PALETTE_ID).unwrap();
+    let mut units = archive("UNIT", AssetKind::SpriteContainer, data);
+    let unit = units.asset("UNIT").unwrap().clone();
+    let info = units.describe(&unit).unwrap();
+
+    let mut manifest = Manifest::new();
+    let frame_files = [
+        "res/sprite-container/UNIT/0.PNG",
+        "res/sprite-container/UNIT/1.PNG",
+    ];
+    let files: Vec<_> = frame_files.iter().map(|file| dir.join(file)).collect();
+    manifest.add("MAX2.RES", &unit, info, &files, &dir);
+
+    let mut icon = image(2, 2, vec![1, 2, 3, 4]);
+    icon.origin_x = -3;
+    icon.palette_id = Some(PALETTE_ID);
+    let data = encode_img(&icon).unwrap();
+    let mut icons = archive("ICON", AssetKind::Image, data);
+    let icon = icons.asset("ICON").unwrap().clone();
+    let info = icons.describe(&icon).unwrap();
+    let files = [dir.join("res/image/ICON.PNG")];
+    manifest.add("MAX2.RES", &icon, info, &files, &dir);
+
+    let path = dir.join("manifest.json");
+    manifest.write(&path).unwrap();
+    let written: Value = serde_json::from_reader(File::open(&path).unwrap())
+        .unwrap();
+    assert_eq!(written["assets"][0], json!({
+        "archive": "MAX2.RES",
+        "name": "UNIT",
+        "kind": "sprite-container",
+        "type_id": 2,
+        "offset": 14,
+        "length": unit.length,
+        "palette_id": PALETTE_ID,
+        "frames": [
+            { "width": 40, "height": 5, "hotspot_x": -2, "hotspot_y": 7 },
+            { "width": 3, "height": 2, "hotspot_x": 1, "hotspot_y": 0 },
+        ],
+        "files": frame_files,
+    }));
+    assert_eq!(written["assets"][1]["kind"], "image");
+    assert_eq!(written["assets"][1]["frames"][0]["hotspot_x"], -3);
+    assert!(written["assets"][1].get("unknown").is_none());
+
+    let files = Manifest::read_files(&path).unwrap();
+    assert_eq!(files.len(), 2);
+    assert_eq!(files[0], frame_files);
+    assert_eq!(files[1], ["res/image/ICON.PNG"]);
+
+    remove_dir_all(&dir).unwrap();
+}
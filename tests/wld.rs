@@ -0,0 +1,323 @@
+use std::fs::{read_to_string, remove_dir_all, File};
+use std::io::Cursor;
+use image::{GenericImageView, Rgb, RgbImage};
+use serde_json::json;
+
+use max2::ExtractOptions;
+use max2::typemap::{render_typemap, TypeColors};
+use max2::wld::extract_wld_data;
+use max2::wldbuilder::build_world;
+use max2::world::{typemap_halves, SectorType, World, SECTOR_EDGE};
+
+mod common;
+
+use common::temp_dir;
+
+/// Synthetic WLD map, every sector is drawn with its own tile.
+struct Fixture {
+    width: u32,
+    height: u32,
+}
+
+impl Fixture {
+    /// Sectors use tiles in reverse order, so tile ids differ from sectors.
+    fn tile_id(&self, x: u32, y: u32) -> u16 {
+        (self.width * self.height - 1 - (x + y * self.width)) as u16
+    }
+
+    /// Every 8x8 block of tile has different palette index.
+    fn tile_pixel(tile_id: u16, x: u32, y: u32) -> u8 {
+        (tile_id as u32 * 16 + x / 8 + (y / 8) * 4) as u8
+    }
+
+    /// Pattern that changes when map is transposed or shifted.
+    fn sector_type(&self, x: u32, y: u32) -> u8 {
+        ((x * 3 + y) % 6) as u8
+    }
+
+    fn vertex_height(&self, x: u32, y: u32) -> u16 {
+        (x * 100 + y) as u16
+    }
+
+    fn build(&self) -> Vec<u8> {
+        let (width, height) = (self.width, self.height);
+        let mut data: Vec<u8> = Vec::new();
+        data.extend_from_slice(b"WLD\x01\x00");
+        data.extend_from_slice(&(width as u16).to_le_bytes());
+        data.extend_from_slice(&(height as u16).to_le_bytes());
+
+        // Minimap
+        for i in 0..width * height {
+            data.push(i as u8);
+        }
+
+        for y in 0..height {
+            for x in 0..width {
+                data.extend_from_slice(&self.tile_id(x, y).to_le_bytes());
+            }
+        }
+
+        for y in 0..=height {
+            for x in 0..=width {
+                let vertex = self.vertex_height(x, y);
+                data.extend_from_slice(&vertex.to_le_bytes());
+            }
+        }
+
+        let tiles_count = (width * height) as u16;
+        data.extend_from_slice(&tiles_count.to_le_bytes());
+        for tile_id in 0..tiles_count {
+            for y in 0..SECTOR_EDGE {
+                for x in 0..SECTOR_EDGE {
+                    data.push(Fixture::tile_pixel(tile_id, x, y));
+                }
+            }
+        }
+
+        // Palette index i is (i, 255 - i, 0) color
+        for i in 0..=255u8 {
+            data.extend_from_slice(&[i, 255 - i, 0]);
+        }
+
+        let widths = typemap_halves(width);
+        let heights = typemap_halves(height);
+        for y_quarter in 0..2 {
+            for x_quarter in 0..2 {
+                let left = if x_quarter == 1 { widths[0] } else { 0 };
+                let top = if y_quarter == 1 { heights[0] } else { 0 };
+                for y in top..top + heights[y_quarter] {
+                    for x in left..left + widths[x_quarter] {
+                        data.push(self.sector_type(x, y));
+                    }
+                }
+            }
+        }
+
+        data
+    }
+
+    fn world(&self) -> World {
+        World::from_reader(&mut Cursor::new(self.build())).unwrap()
+    }
+}
+
+const FIXTURES: [Fixture; 5] = [
+    Fixture { width: 2, height: 2 },
+    Fixture { width: 4, height: 2 },
+    Fixture { width: 2, height: 5 },
+    Fixture { width: 3, height: 5 },
+    Fixture { width: 5, height: 1 },
+];
+
+#[test]
+fn reads_sectors_of_non_square_maps() {
+    for fixture in &FIXTURES {
+        let world = fixture.world();
+        assert_eq!(world.width, fixture.width);
+        assert_eq!(world.height, fixture.height);
+
+        for y in 0..fixture.height {
+            for x in 0..fixture.width {
+                assert_eq!(
+                    world.sector_tile_id(x, y),
+                    Some(fixture.tile_id(x, y))
+                );
+                assert_eq!(
+                    world.minimap_index(x, y),
+                    Some((x + y * fixture.width) as u8)
+                );
+            }
+        }
+        assert_eq!(world.sector_tile_id(fixture.width, 0), None);
+        assert_eq!(world.sector_tile_id(0, fixture.height), None);
+    }
+}
+
+#[test]
+fn reads_sector_types_of_odd_maps() {
+    for fixture in &FIXTURES {
+        let world = fixture.world();
+        for y in 0..fixture.height {
+            for x in 0..fixture.width {
+                let type_ = fixture.sector_type(x, y);
+                let expected = SectorType::from_value(type_);
+                assert_eq!(
+                    world.sector_type(x, y),
+                    Some(expected),
+                    "sector {}x{} of {}x{} map",
+                    x, y, fixture.width, fixture.height
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn reads_vertex_heights() {
+    for fixture in &FIXTURES {
+        let world = fixture.world();
+        for y in 0..=fixture.height {
+            for x in 0..=fixture.width {
+                assert_eq!(
+                    world.vertex_height(x, y),
+                    Some(fixture.vertex_height(x, y))
+                );
+            }
+        }
+
+        let (x, y) = (fixture.width - 1, fixture.height - 1);
+        assert_eq!(
+            world.sector_heights(x, y),
+            Some([
+                fixture.vertex_height(x, y),
+                fixture.vertex_height(x + 1, y),
+                fixture.vertex_height(x, y + 1),
+                fixture.vertex_height(x + 1, y + 1),
+            ])
+        );
+    }
+}
+
+#[test]
+fn converts_coordinates() {
+    let world = Fixture { width: 3, height: 5 }.world();
+    assert_eq!(world.pixel_to_sector(95, 159), Some((2, 4)));
+    assert_eq!(world.pixel_to_sector(96, 0), None);
+    assert_eq!(world.sector_to_pixel(2, 4), Some((64, 128)));
+    assert_eq!(world.sector_to_pixel(3, 0), None);
+    assert_eq!(world.pixel_to_vertex(47, 48), Some((1, 2)));
+    assert_eq!(world.vertex_to_pixel(3, 5), Some((96, 160)));
+    assert_eq!(world.vertex_to_sector(3, 0), None);
+}
+
+#[test]
+fn places_map_pixels_in_sectors() {
+    for fixture in &FIXTURES {
+        let world = fixture.world();
+        for y in 0..fixture.height {
+            for x in 0..fixture.width {
+                let tile_id = fixture.tile_id(x, y);
+                let (left, top) = world.sector_to_pixel(x, y).unwrap();
+                assert_eq!(
+                    world.pixel(left + 31, top + 9),
+                    Some(Fixture::tile_pixel(tile_id, 31, 9))
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn renders_non_square_maps() {
+    for fixture in &FIXTURES {
+        let name = format!("{}x{}", fixture.width, fixture.height);
+        let dir = temp_dir(&format!("wld-{}", name));
+        let mut wld_file = Cursor::new(fixture.build());
+        let options = ExtractOptions::default();
+        extract_wld_data(&mut wld_file, &dir, &options).unwrap();
+
+        let map = image::open(dir.join("full.png")).unwrap();
+        assert_eq!(
+            map.dimensions(),
+            (fixture.width * SECTOR_EDGE, fixture.height * SECTOR_EDGE)
+        );
+        for y in 0..fixture.height {
+            for x in 0..fixture.width {
+                let tile_id = fixture.tile_id(x, y);
+                let samples = [(0, 0), (31, 0), (8, 31), (20, 20)];
+                for &(tile_x, tile_y) in &samples {
+                    let pixel = map.get_pixel(
+                        x * SECTOR_EDGE + tile_x, y * SECTOR_EDGE + tile_y
+                    );
+                    let index = Fixture::tile_pixel(tile_id, tile_x, tile_y);
+                    assert_eq!(pixel[0], index, "{} at {}x{}", name, x, y);
+                }
+            }
+        }
+
+        let typemap = image::open(dir.join("typemap.png")).unwrap();
+        assert_eq!(typemap.dimensions(), (fixture.width, fixture.height));
+
+        remove_dir_all(&dir).unwrap();
+    }
+}
+
+#[test]
+fn writes_typemap_csv_json_and_legend() {
+    let fixture = Fixture { width: 3, height: 5 };
+    let mut world = fixture.world();
+    // Type extractor doesn't know
+    world.types[0] = SectorType::from_value(9);
+    let mut colors = TypeColors::default();
+    colors.set(SectorType::Water, [0, 0, 255]);
+
+    let dir = temp_dir("typemap");
+    let warnings = render_typemap(&world, &colors, &dir).unwrap();
+    assert_eq!(
+        warnings,
+        ["unknown sector type 9 in 1 sectors, drawn black in typemap"]
+    );
+
+    let csv = read_to_string(dir.join("typemap.csv")).unwrap();
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines, ["9,3,0", "1,4,1", "2,5,2", "3,0,3", "4,1,4"]);
+
+    let file = File::open(dir.join("typemap.json")).unwrap();
+    let grid: serde_json::Value = serde_json::from_reader(file).unwrap();
+    assert_eq!(grid["width"], 3);
+    assert_eq!(grid["height"], 5);
+    assert_eq!(grid["types"][0], json!([9, 3, 0]));
+    let legend = grid["legend"].as_array().unwrap();
+    assert_eq!(legend.len(), 7);
+    assert_eq!(legend[1], json!({
+        "value": 1, "name": "water", "color": "#0000ff", "count": 3
+    }));
+    assert_eq!(legend[6], json!({
+        "value": 9, "name": "unknown-9", "count": 1
+    }));
+
+    // Water sector at 0x1 is drawn with color set for it
+    let typemap = image::open(dir.join("typemap.png")).unwrap();
+    assert_eq!(typemap.get_pixel(0, 1).data, [0, 0, 255, 255]);
+    assert_eq!(typemap.get_pixel(0, 0).data, [0, 0, 0, 255]);
+
+    // Swatch with label for every legend entry
+    let legend = image::open(dir.join("legend.png")).unwrap();
+    assert_eq!(legend.height(), 4 + 7 * (16 + 4));
+
+    remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn builds_map_sharing_tiles_of_identical_sectors() {
+    let mut palette = [0u8; 768];
+    for (i, color) in palette.chunks_mut(3).enumerate() {
+        color.copy_from_slice(&[i as u8, 255 - i as u8, 0]);
+    }
+    // Sectors on checkerboard's dark squares are drawn the same way
+    let index = |x: u32, y: u32| {
+        let (sector_x, sector_y) = (x / SECTOR_EDGE, y / SECTOR_EDGE);
+        if (sector_x + sector_y) % 2 == 0 {
+            (x % SECTOR_EDGE + y % SECTOR_EDGE) as u8
+        } else {
+            (200 + sector_x + sector_y * 3) as u8
+        }
+    };
+    let map = RgbImage::from_fn(3 * SECTOR_EDGE, 2 * SECTOR_EDGE, |x, y| {
+        Rgb([index(x, y), 255 - index(x, y), 0])
+    });
+
+    let heights = vec![0; 12];
+    let types = vec![SectorType::Grass; 6];
+    let world = build_world(&map, Some(&palette), heights, types, [7, 9])
+        .unwrap();
+    assert_eq!(world.tile_count(), 4);
+    assert_eq!(world.tile_ids, [0, 1, 0, 2, 0, 3]);
+    for (x, y, _) in map.enumerate_pixels() {
+        assert_eq!(world.pixel(x, y), Some(index(x, y)));
+    }
+
+    let mut data: Vec<u8> = Vec::new();
+    world.write(&mut data).unwrap();
+    assert_eq!(&data[..5], b"WLD\x07\x09");
+}